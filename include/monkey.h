@@ -0,0 +1,84 @@
+/* C API for embedding the Monkey interpreter; see src/ffi.rs.
+ *
+ * Interpreters, results, values and strings returned by these functions are
+ * owned by the caller and released with the matching _free function. Values
+ * and strings obtained from a result belong to that result.
+ */
+#ifndef MONKEY_H
+#define MONKEY_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct MonkeyInterpreter MonkeyInterpreter;
+typedef struct MonkeyResult MonkeyResult;
+typedef struct MonkeyValue MonkeyValue;
+
+typedef enum {
+    MONKEY_NULL,
+    MONKEY_INTEGER,
+    MONKEY_BOOLEAN,
+    MONKEY_STRING,
+    MONKEY_ARRAY,
+    MONKEY_HASH,
+    MONKEY_BUILTIN,
+} MonkeyType;
+
+typedef enum {
+    MONKEY_ERROR_NONE,
+    MONKEY_ERROR_SYNTAX,
+    MONKEY_ERROR_RUNTIME,
+    MONKEY_ERROR_STACK_OVERFLOW,
+    MONKEY_ERROR_OUT_OF_FUEL,
+    MONKEY_ERROR_TIMEOUT,
+    MONKEY_ERROR_OUT_OF_MEMORY,
+    MONKEY_ERROR_PERMISSION_DENIED,
+} MonkeyErrorKind;
+
+/* A builtin implemented in C. Returns a new value, or NULL after pointing
+ * *error at a message that stays valid until the callback returns. */
+typedef MonkeyValue *(*MonkeyCallback)(void *user_data, const MonkeyValue *const *args,
+                                       size_t nargs, const char **error);
+
+MonkeyInterpreter *monkey_interpreter_new(void);
+void monkey_interpreter_free(MonkeyInterpreter *interp);
+
+/* Never returns NULL; check monkey_result_is_ok. */
+MonkeyResult *monkey_eval(MonkeyInterpreter *interp, const char *source);
+
+/* Returns false if name is not valid UTF-8. */
+bool monkey_register_builtin(MonkeyInterpreter *interp, const char *name, size_t arity,
+                             MonkeyCallback callback, void *user_data);
+
+void monkey_result_free(MonkeyResult *result);
+bool monkey_result_is_ok(const MonkeyResult *result);
+/* NULL if the evaluation failed. */
+const MonkeyValue *monkey_result_value(const MonkeyResult *result);
+/* NULL if the evaluation succeeded. */
+const char *monkey_result_error(const MonkeyResult *result);
+/* MONKEY_ERROR_NONE if the evaluation succeeded. */
+MonkeyErrorKind monkey_result_error_kind(const MonkeyResult *result);
+
+MonkeyValue *monkey_value_int(int64_t value);
+/* NULL if value is not valid UTF-8. */
+MonkeyValue *monkey_value_string(const char *value);
+void monkey_value_free(MonkeyValue *value);
+MonkeyType monkey_value_type(const MonkeyValue *value);
+bool monkey_value_as_int(const MonkeyValue *value, int64_t *out);
+/* NULL if value is not a string; free with monkey_string_free. */
+char *monkey_value_as_string(const MonkeyValue *value);
+/* Free with monkey_string_free. */
+char *monkey_value_inspect(const MonkeyValue *value);
+
+void monkey_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
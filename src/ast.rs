@@ -1,5 +1,10 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use serde_json;
 use std::fmt;
 use token;
+use token::Span;
 
 #[derive(Debug)]
 pub enum Node {
@@ -9,43 +14,181 @@ pub enum Node {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Statement {
     Let(Box<LetStatement>),
     Expression(Box<ExpressionStatement>),
+    Import(Box<ImportStatement>),
 }
 
 impl fmt::Display for Statement {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        let s = format!("{}", self);
-        write!(f, "{}", s)
+        match self {
+            Statement::Let(stmt) => write!(f, "let {} = {};", stmt.name, stmt.value),
+            Statement::Expression(stmt) => write!(f, "{}", stmt),
+            Statement::Import(stmt) => write!(f, "import \"{}\" as {};", stmt.path, stmt.alias),
+        }
     }
 }
 
+/// An expression. Every node records its span in the source, and
+/// parentheses belong to the span of the expression they enclose.
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Expression {
-    Identifier(String),
-    Integer(i64),
+    Identifier(Identifier),
+    Integer(IntegerLiteral),
     Prefix(Box<PrefixExpression>),
     Infix(Box<InfixExpression>),
+    Call(Box<CallExpression>),
+    Member(Box<MemberExpression>),
 }
 
-#[derive(Debug)]
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Expression::Identifier(ident) => write!(f, "{}", ident.name),
+            Expression::Integer(int) => write!(f, "{}", int.value),
+            Expression::Prefix(expr) => write!(f, "({}{})", expr.operator.literal(), expr.right),
+            Expression::Infix(expr) => {
+                write!(
+                    f,
+                    "({} {} {})",
+                    expr.left,
+                    expr.operator.literal(),
+                    expr.right
+                )
+            }
+            Expression::Call(expr) => {
+                let args: Vec<String> = expr.arguments.iter().map(|a| a.to_string()).collect();
+                write!(f, "{}({})", expr.function, args.join(", "))
+            }
+            Expression::Member(expr) => write!(f, "{}.{}", expr.object, expr.name),
+        }
+    }
+}
+
+impl Expression {
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Identifier(ident) => ident.span,
+            Expression::Integer(int) => int.span,
+            Expression::Prefix(expr) => expr.span,
+            Expression::Infix(expr) => expr.span,
+            Expression::Call(expr) => expr.span,
+            Expression::Member(expr) => expr.span,
+        }
+    }
+
+    /// Returns the expression with its span replaced by `span`.
+    pub fn with_span(mut self, span: Span) -> Expression {
+        match self {
+            Expression::Identifier(ref mut ident) => ident.span = span,
+            Expression::Integer(ref mut int) => int.span = span,
+            Expression::Prefix(ref mut expr) => expr.span = span,
+            Expression::Infix(ref mut expr) => expr.span = span,
+            Expression::Call(ref mut expr) => expr.span = span,
+            Expression::Member(ref mut expr) => expr.span = span,
+        }
+        self
+    }
+
+    /// Returns how many expressions deep this one nests, counting itself.
+    pub fn depth(&self) -> usize {
+        let inner = match self {
+            Expression::Identifier(_) | Expression::Integer(_) => 0,
+            Expression::Prefix(expr) => expr.right.depth(),
+            Expression::Infix(expr) => expr.left.depth().max(expr.right.depth()),
+            Expression::Call(expr) => expr
+                .arguments
+                .iter()
+                .map(Expression::depth)
+                .fold(expr.function.depth(), usize::max),
+            Expression::Member(expr) => expr.object.depth(),
+        };
+        inner + 1
+    }
+}
+
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Program {
     pub statements: Vec<Statement>,
 }
 
 impl Program {
     pub fn new() -> Program {
-        Program {
-            statements: Vec::new(),
+        Program::default()
+    }
+
+    /// Reads a program written by `monkey parse --json`, failing like the
+    /// parser does if its expressions nest deeper than `max_depth`.
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str, max_depth: usize) -> Result<Program, String> {
+        let overflow = || {
+            format!(
+                "stack overflow: expression nested deeper than {}",
+                max_depth
+            )
+        };
+        // serde_json stops at 128 levels, far short of what the parser
+        // accepts, so its limit is lifted. An expression nests the JSON at
+        // most three levels deeper than its parent, which bounds how deep
+        // deserializing may recurse before the exact check below.
+        if json_depth(json) > 3 * (max_depth + 2) {
+            return Err(overflow());
         }
+        let mut de = serde_json::Deserializer::from_str(json);
+        de.disable_recursion_limit();
+        let program = Program::deserialize(&mut de)
+            .and_then(|program| de.end().map(|_| program))
+            .map_err(|e| e.to_string())?;
+
+        let too_deep = program.statements.iter().any(|stmt| match stmt {
+            Statement::Let(stmt) => stmt.value.depth() > max_depth,
+            Statement::Expression(stmt) => stmt.expression.depth() > max_depth,
+            Statement::Import(_) => false,
+        });
+        if too_deep {
+            return Err(overflow());
+        }
+        Ok(program)
     }
 }
 
+/// Returns how deeply objects and arrays nest in `json`, without parsing it.
+#[cfg(feature = "serde")]
+fn json_depth(json: &str) -> usize {
+    let (mut depth, mut max) = (0usize, 0);
+    let (mut in_string, mut escaped) = (false, false);
+    for c in json.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => {
+                depth += 1;
+                max = max.max(depth);
+            }
+            '}' | ']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    max
+}
+
 impl fmt::Display for Program {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let stmts: Vec<String> = (*self.statements)
-            .into_iter()
+        let stmts: Vec<String> = self
+            .statements
+            .iter()
             .map(|stmt| stmt.to_string())
             .collect();
         write!(f, "{}", stmts.join(""))
@@ -53,34 +196,152 @@ impl fmt::Display for Program {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct LetStatement {
     pub name: String,
     pub value: Expression,
+    /// From `let` through the closing `;`.
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ExpressionStatement {
     pub expression: Expression,
+    /// From the start of the expression through the closing `;`.
+    pub span: Span,
+}
+
+/// Binds the module at `path` to `alias`. The session loads modules before
+/// the program runs; see `session::Session::eval_program`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ImportStatement {
+    pub path: String,
+    pub alias: String,
+    /// From `import` through the closing `;`.
+    pub span: Span,
 }
 
 impl fmt::Display for ExpressionStatement {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let s = format!("{}", self);
-        write!(f, "{}", s)
+        write!(f, "{};", self.expression)
     }
 }
 
 // Expression
 
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Identifier {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct IntegerLiteral {
+    pub value: i64,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct InfixExpression {
-    pub operator: token::Token,
+    pub operator: token::TokenKind,
     pub left: Expression,
     pub right: Expression,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct PrefixExpression {
-    pub operator: token::Token,
+    pub operator: token::TokenKind,
     pub right: Expression,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CallExpression {
+    pub function: Expression,
+    pub arguments: Vec<Expression>,
+    /// From the start of the function through the closing paren.
+    pub span: Span,
+}
+
+/// `object.name`, e.g. a binding exported by an imported module.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MemberExpression {
+    pub object: Expression,
+    pub name: String,
+    pub span: Span,
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+    use lexer::Lexer;
+    use parser::{self, Parser};
+    use serde_json;
+    use session::Session;
+
+    #[test]
+    fn ast_round_trip() {
+        let source = "let a = -(1 + 2); f(a, 3);";
+        let program = Parser::new(Lexer::new(source)).parse_program().unwrap();
+        let json = serde_json::to_string(&program).unwrap();
+        assert!(json.starts_with(
+            r#"{"statements":[{"Let":{"name":"a","value":{"Prefix":{"operator":"Minus","#
+        ));
+        assert!(json.contains(r#""span":{"start":0,"end":17}"#));
+
+        // Sub-expressions carry spans too, e.g. the `3` argument.
+        assert!(json.contains(r#"{"Integer":{"value":3,"span":{"start":23,"end":24}}}"#));
+
+        // A tool rewrites the call into a plain expression.
+        let call = r#"{"Call":{"function":{"Identifier":{"name":"f","span":{"start":18,"end":19}}},"arguments":[{"Identifier":{"name":"a","span":{"start":20,"end":21}}},{"Integer":{"value":3,"span":{"start":23,"end":24}}}],"span":{"start":18,"end":25}}}"#;
+        assert!(json.contains(call), "{}", json);
+        let json = json.replace(
+            call,
+            r#"{"Identifier":{"name":"a","span":{"start":18,"end":19}}}"#,
+        );
+        let program: Program = serde_json::from_str(&json).unwrap();
+        assert_eq!("let a = (-(1 + 2));a;", program.to_string());
+        let result = Session::default().eval_program(program).unwrap();
+        assert_eq!("-3", result.inspect());
+    }
+
+    #[test]
+    fn deep_programs_round_trip() {
+        let depth = parser::DEFAULT_MAX_DEPTH;
+        let source = format!("{}1;", "-".repeat(depth - 1));
+        let program = Parser::new(Lexer::new(&source)).parse_program().unwrap();
+        let json = serde_json::to_string(&program).unwrap();
+        let program = Program::from_json(&json, depth).unwrap();
+        assert_eq!(depth, depth_of(&program));
+        let result = Session::default().eval_program(program).unwrap();
+        assert_eq!("-1", result.inspect());
+
+        let err = Program::from_json(&json, depth - 1).unwrap_err();
+        assert_eq!(
+            format!(
+                "stack overflow: expression nested deeper than {}",
+                depth - 1
+            ),
+            err
+        );
+        // Nesting far past the limit fails before deserializing.
+        let json = format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+        assert!(Program::from_json(&json, depth).is_err());
+    }
+
+    fn depth_of(program: &Program) -> usize {
+        match program.statements[0] {
+            Statement::Expression(ref stmt) => stmt.expression.depth(),
+            _ => panic!("not an expression statement"),
+        }
+    }
 }
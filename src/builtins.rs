@@ -0,0 +1,264 @@
+use capabilities::{self, Capabilities};
+use convert::{self, NativeFunction};
+use evaluator::{EvalError, EvalResult};
+use object::Object;
+use std::cell::RefCell;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+pub type BuiltinFunction = Rc<dyn Fn(&[Object]) -> EvalResult>;
+
+/// A function implemented in Rust that scripts can call by name.
+#[derive(Clone)]
+pub struct Builtin {
+    pub name: String,
+    pub arity: usize,
+    pub doc: String,
+    pub function: BuiltinFunction,
+}
+
+impl Builtin {
+    /// Calls the function after checking the number of arguments.
+    pub fn call(&self, args: &[Object]) -> EvalResult {
+        if args.len() != self.arity {
+            return Err(EvalError::new(format!(
+                "wrong number of arguments to {}: got {}, want {}",
+                self.name,
+                args.len(),
+                self.arity
+            )));
+        }
+        (self.function)(args)
+    }
+}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Builtin")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish()
+    }
+}
+
+/// The builtins a host has registered, in registration order. The VM refers
+/// to them by that position.
+#[derive(Debug, Clone, Default)]
+pub struct Builtins {
+    list: Vec<Builtin>,
+}
+
+impl Builtins {
+    pub fn new() -> Builtins {
+        Builtins::default()
+    }
+
+    /// The builtins every session starts with, doing only the I/O that
+    /// `capabilities` grants. Their order is part of the bytecode format.
+    pub fn core(capabilities: Capabilities) -> Builtins {
+        let mut builtins = Builtins::new();
+        builtins.register("puts", 1, PUTS_DOC, puts(capabilities.output));
+        builtins.register("read_line", 0, READ_LINE_DOC, read_line(capabilities.input));
+        let roots = capabilities.fs_roots;
+        builtins.register("read_file", 1, READ_FILE_DOC, read_file(roots.clone()));
+        builtins.register("write_file", 2, WRITE_FILE_DOC, write_file(roots));
+        builtins
+    }
+
+    /// Adds a builtin and returns its index. Registering a name again
+    /// replaces the earlier function but keeps its index.
+    pub fn register<F>(&mut self, name: &str, arity: usize, doc: &str, function: F) -> usize
+    where
+        F: Fn(&[Object]) -> EvalResult + 'static,
+    {
+        self.insert(Builtin {
+            name: name.to_string(),
+            arity,
+            doc: doc.to_string(),
+            function: Rc::new(function),
+        })
+    }
+
+    /// Adds a builtin whose arity and argument conversions follow from the
+    /// parameter types of `function`.
+    pub fn register_fn<F, Args>(&mut self, name: &str, doc: &str, function: F) -> usize
+    where
+        F: NativeFunction<Args>,
+    {
+        self.insert(Builtin {
+            name: name.to_string(),
+            arity: F::arity(),
+            doc: doc.to_string(),
+            function: function.into_builtin_function(name),
+        })
+    }
+
+    /// Adds an existing builtin, replacing one of the same name like
+    /// `register`.
+    pub fn insert(&mut self, builtin: Builtin) -> usize {
+        match self.index(&builtin.name) {
+            Some(index) => {
+                self.list[index] = builtin;
+                index
+            }
+            None => {
+                self.list.push(builtin);
+                self.list.len() - 1
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Builtin> {
+        self.list.iter().find(|b| b.name == name)
+    }
+
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.list.iter().position(|b| b.name == name)
+    }
+
+    pub fn by_index(&self, index: usize) -> Option<&Builtin> {
+        self.list.get(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Builtin> {
+        self.list.iter()
+    }
+}
+
+pub const PUTS_DOC: &str = "Prints a value followed by a newline and returns null.";
+pub const READ_LINE_DOC: &str = "Reads a line of input without its newline, or null at the end.";
+pub const READ_FILE_DOC: &str = "Returns the contents of a file.";
+pub const WRITE_FILE_DOC: &str = "Replaces the contents of a file with a string and returns null.";
+
+/// Returns the `puts` builtin, writing to `output` if there is one.
+pub fn puts(output: Option<Box<dyn io::Write>>) -> impl Fn(&[Object]) -> EvalResult {
+    let output = output.map(RefCell::new);
+    move |args| {
+        let output = output
+            .as_ref()
+            .ok_or_else(|| EvalError::permission_denied("puts has no output sink".to_string()))?;
+        writeln!(output.borrow_mut(), "{}", args[0].inspect())
+            .map_err(|e| EvalError::new(format!("puts: {}", e)))?;
+        Ok(Object::Null)
+    }
+}
+
+/// Returns the `read_line` builtin, reading from `input` if there is one.
+pub fn read_line(input: Option<Box<dyn io::BufRead>>) -> impl Fn(&[Object]) -> EvalResult {
+    let input = input.map(RefCell::new);
+    move |_| {
+        let input = input.as_ref().ok_or_else(|| {
+            EvalError::permission_denied("read_line has no input source".to_string())
+        })?;
+        let mut line = String::new();
+        let n = input
+            .borrow_mut()
+            .read_line(&mut line)
+            .map_err(|e| EvalError::new(format!("read_line: {}", e)))?;
+        if n == 0 {
+            return Ok(Object::Null);
+        }
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Object::Str(line))
+    }
+}
+
+/// Returns the `read_file` builtin, limited to files below `roots`.
+pub fn read_file(roots: Vec<PathBuf>) -> impl Fn(&[Object]) -> EvalResult {
+    move |args| {
+        let path: String = convert::argument("read_file", args, 0)?;
+        let resolved = capabilities::check_path(&roots, &path)?;
+        fs::read_to_string(resolved)
+            .map(Object::Str)
+            .map_err(|e| EvalError::new(format!("read_file: {}: {}", path, e)))
+    }
+}
+
+/// Returns the `write_file` builtin, limited to files below `roots`.
+pub fn write_file(roots: Vec<PathBuf>) -> impl Fn(&[Object]) -> EvalResult {
+    move |args| {
+        let path: String = convert::argument("write_file", args, 0)?;
+        let contents: String = convert::argument("write_file", args, 1)?;
+        let resolved = capabilities::check_path(&roots, &path)?;
+        fs::write(resolved, contents)
+            .map(|_| Object::Null)
+            .map_err(|e| EvalError::new(format!("write_file: {}: {}", path, e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evaluator::ErrorKind;
+
+    #[test]
+    fn register_and_call() {
+        let mut builtins = Builtins::new();
+        let index = builtins.register("double", 1, "Doubles an integer.", |args| match args[0] {
+            Object::Int(i) => Ok(Object::Int(i * 2)),
+            ref obj => Err(EvalError::new(format!("double: got {}", obj.type_name()))),
+        });
+        assert_eq!(0, index);
+
+        let double = builtins.get("double").unwrap();
+        assert_eq!("Doubles an integer.", double.doc);
+        assert_eq!("2206", double.call(&[Object::Int(1103)]).unwrap().inspect());
+        let err = double.call(&[]).unwrap_err();
+        assert_eq!(
+            "wrong number of arguments to double: got 0, want 1",
+            err.message
+        );
+
+        builtins.register("other", 0, "", |_| Ok(Object::Int(0)));
+        let index = builtins.register("double", 0, "", |_| Ok(Object::Int(2)));
+        assert_eq!(0, index);
+        assert_eq!(
+            "2",
+            builtins.by_index(0).unwrap().call(&[]).unwrap().inspect()
+        );
+    }
+
+    #[test]
+    fn puts_writes_a_line() {
+        let output = ::interpreter::CapturedOutput::new();
+        let puts = puts(Some(Box::new(output.clone())));
+        assert_eq!("null", puts(&[Object::Int(1103)]).unwrap().inspect());
+        puts(&[Object::Str("monkey".to_string())]).unwrap();
+        assert_eq!("1103\nmonkey\n", output.contents());
+    }
+
+    #[test]
+    fn io_needs_capabilities() {
+        let builtins = Builtins::core(Capabilities::new());
+        let call = |name: &str, args: &[Object]| builtins.get(name).unwrap().call(args);
+        let path = Object::Str("monkey.txt".to_string());
+        for (name, args) in [
+            ("puts", vec![Object::Int(1)]),
+            ("read_line", vec![]),
+            ("read_file", vec![path.clone()]),
+            ("write_file", vec![path.clone(), path.clone()]),
+        ] {
+            let err = call(name, &args).unwrap_err();
+            assert_eq!(ErrorKind::PermissionDenied, err.kind, "{}", name);
+        }
+    }
+
+    #[test]
+    fn read_lines() {
+        let mut capabilities = Capabilities::new();
+        capabilities.set_input(Box::new(io::Cursor::new("one\r\ntwo")));
+        let builtins = Builtins::core(capabilities);
+        let read_line = builtins.get("read_line").unwrap();
+        assert_eq!("one", read_line.call(&[]).unwrap().inspect());
+        assert_eq!("two", read_line.call(&[]).unwrap().inspect());
+        assert_eq!("null", read_line.call(&[]).unwrap().inspect());
+    }
+}
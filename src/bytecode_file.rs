@@ -0,0 +1,291 @@
+use code::{read_operands, Opcode};
+use compiler::Bytecode;
+use object::Object;
+use std::convert::TryFrom;
+
+/// The first bytes of every compiled Monkey file.
+pub const MAGIC: &[u8; 4] = b"MKC\0";
+
+/// The format version written by this build. Files with any other version
+/// are rejected rather than guessed at. Bump it whenever an opcode or
+/// constant encoding changes; version 2 added builtin calls, version 3 member
+/// access and string constants.
+pub const VERSION: u16 = 3;
+
+const HEADER_LEN: usize = 14;
+const TAG_INTEGER: u8 = 0;
+const TAG_STRING: u8 = 1;
+
+#[derive(Debug)]
+pub struct DecodeError {
+    pub message: String,
+}
+
+pub type DecodeResult<T> = Result<T, DecodeError>;
+
+/// Encodes bytecode as
+///
+/// ```text
+/// magic "MKC\0" | version: u16 | crc32: u32 | payload length: u32 | payload
+/// payload = constant count: u32 | constants | instruction length: u32 | instructions
+/// constant = tag: u8 | value (integers: i64, strings: length: u32 | UTF-8)
+/// ```
+///
+/// with all numbers big-endian and the checksum taken over the payload.
+pub fn encode(bytecode: &Bytecode) -> Vec<u8> {
+    let mut payload = Vec::new();
+    payload.extend_from_slice(&(bytecode.constants.len() as u32).to_be_bytes());
+    for constant in &bytecode.constants {
+        match constant {
+            Object::Int(i) => {
+                payload.push(TAG_INTEGER);
+                payload.extend_from_slice(&i.to_be_bytes());
+            }
+            Object::Str(s) => {
+                payload.push(TAG_STRING);
+                payload.extend_from_slice(&(s.len() as u32).to_be_bytes());
+                payload.extend_from_slice(s.as_bytes());
+            }
+            // The compiler only adds integer literals and member names to
+            // the constant pool.
+            obj => unreachable!("{} in the constant pool", obj.type_name()),
+        }
+    }
+    payload.extend_from_slice(&(bytecode.instructions.len() as u32).to_be_bytes());
+    payload.extend_from_slice(&bytecode.instructions);
+
+    let mut out = Vec::with_capacity(HEADER_LEN + payload.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_be_bytes());
+    out.extend_from_slice(&crc32(&payload).to_be_bytes());
+    out.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    out.extend_from_slice(&payload);
+    out
+}
+
+/// Reports whether `bytes` start like a compiled Monkey file.
+pub fn is_bytecode_file(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Decodes and validates a file written by `encode`.
+pub fn decode(bytes: &[u8]) -> DecodeResult<Bytecode> {
+    if !is_bytecode_file(bytes) {
+        return Err(error("not a compiled monkey file"));
+    }
+    let mut r = Reader {
+        bytes,
+        offset: MAGIC.len(),
+    };
+
+    let version = r.read_u16()?;
+    if version != VERSION {
+        return Err(error(&format!(
+            "unsupported bytecode version {} (this build reads version {}); recompile the script",
+            version, VERSION
+        )));
+    }
+    let checksum = r.read_u32()?;
+    let len = r.read_u32()? as usize;
+    let payload = r.read_bytes(len)?;
+    if crc32(payload) != checksum {
+        return Err(error("checksum mismatch, the file is corrupt"));
+    }
+
+    let mut r = Reader {
+        bytes: payload,
+        offset: 0,
+    };
+    let count = r.read_u32()? as usize;
+    let mut constants = Vec::new();
+    for _ in 0..count {
+        match r.read_bytes(1)?[0] {
+            TAG_INTEGER => constants.push(Object::Int(r.read_i64()?)),
+            TAG_STRING => {
+                let len = r.read_u32()? as usize;
+                let s = String::from_utf8(r.read_bytes(len)?.to_vec())
+                    .map_err(|_| error("string constant is not valid UTF-8"))?;
+                constants.push(Object::Str(s));
+            }
+            tag => return Err(error(&format!("unknown constant tag {}", tag))),
+        }
+    }
+    let len = r.read_u32()? as usize;
+    let instructions = r.read_bytes(len)?.to_vec();
+
+    validate(&instructions, constants.len())?;
+    Ok(Bytecode {
+        instructions,
+        constants,
+    })
+}
+
+/// Checks that every opcode is defined, every operand is present and every
+/// constant index is in range, so the VM never reads out of bounds.
+fn validate(ins: &[u8], num_constants: usize) -> DecodeResult<()> {
+    let mut offset = 0;
+    while offset < ins.len() {
+        let op = Opcode::try_from(ins[offset]).map_err(|e| error(&e))?;
+        let def = op.definition();
+        let width: usize = def.operand_widths.iter().sum();
+        if offset + 1 + width > ins.len() {
+            return Err(error(&format!("truncated {} at {}", def.name, offset)));
+        }
+        let (operands, read) = read_operands(&def, &ins[offset + 1..]);
+        if op == Opcode::Constant && operands[0] >= num_constants {
+            return Err(error(&format!(
+                "constant {} out of range at {}",
+                operands[0], offset
+            )));
+        }
+        offset += 1 + read;
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read_bytes(&mut self, len: usize) -> DecodeResult<&'a [u8]> {
+        if self.bytes.len() - self.offset < len {
+            return Err(error("unexpected end of file"));
+        }
+        let bytes = &self.bytes[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn read_u16(&mut self) -> DecodeResult<u16> {
+        let b = self.read_bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn read_u32(&mut self) -> DecodeResult<u32> {
+        let mut b = [0; 4];
+        b.copy_from_slice(self.read_bytes(4)?);
+        Ok(u32::from_be_bytes(b))
+    }
+
+    fn read_i64(&mut self) -> DecodeResult<i64> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.read_bytes(8)?);
+        Ok(i64::from_be_bytes(b))
+    }
+}
+
+fn error(message: &str) -> DecodeError {
+    DecodeError {
+        message: message.to_string(),
+    }
+}
+
+/// CRC-32 (IEEE 802.3), computed bitwise.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &b in bytes {
+        crc ^= u32::from(b);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use code::make;
+    use compiler::Compiler;
+    use parser;
+    use vm::VM;
+
+    fn compile(input: &str) -> Bytecode {
+        let mut compiler = Compiler::new();
+        compiler.compile(&parser::parse(input).unwrap()).unwrap();
+        compiler.bytecode()
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytecode = compile("let a = -1103; a * 2;");
+        let decoded = decode(&encode(&bytecode)).unwrap();
+
+        assert_eq!(bytecode.disassemble(), decoded.disassemble());
+        let mut vm = VM::new(decoded);
+        vm.run().unwrap();
+        assert_eq!("-2206", vm.result().inspect());
+    }
+
+    #[test]
+    fn string_constants() {
+        let bytecode = Bytecode {
+            instructions: [make(Opcode::Constant, &[0]), make(Opcode::Pop, &[])].concat(),
+            constants: vec![Object::Str("birthday".to_string())],
+        };
+        let mut vm = VM::new(decode(&encode(&bytecode)).unwrap());
+        vm.run().unwrap();
+        assert_eq!("birthday", vm.result().inspect());
+    }
+
+    #[test]
+    fn checksum() {
+        assert_eq!(0xcbf4_3926, crc32(b"123456789"));
+    }
+
+    #[test]
+    fn reject_invalid_files() {
+        let bytes = encode(&compile("1103;"));
+
+        let mut corrupt = bytes.clone();
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0xff;
+
+        let tests = vec![
+            (b"let a = 1;".to_vec(), "not a compiled monkey file"),
+            (corrupt, "checksum mismatch, the file is corrupt"),
+            (bytes[..bytes.len() - 1].to_vec(), "unexpected end of file"),
+        ];
+
+        for t in tests {
+            assert_eq!(t.1, decode(&t.0).unwrap_err().message);
+        }
+    }
+
+    #[test]
+    fn reject_other_versions() {
+        let bytes = encode(&compile("1103;"));
+
+        for version in [VERSION - 1, VERSION + 1] {
+            let mut file = bytes.clone();
+            file[4..6].copy_from_slice(&version.to_be_bytes());
+            assert_eq!(
+                format!(
+                    "unsupported bytecode version {} (this build reads version {}); recompile the script",
+                    version, VERSION
+                ),
+                decode(&file).unwrap_err().message
+            );
+        }
+    }
+
+    #[test]
+    fn reject_invalid_instructions() {
+        let tests = vec![
+            (vec![255], "opcode 255 undefined"),
+            (make(Opcode::Constant, &[1]), "constant 1 out of range at 0"),
+            (vec![Opcode::Constant as u8, 0], "truncated OpConstant at 0"),
+        ];
+
+        for t in tests {
+            let bytecode = Bytecode {
+                instructions: t.0,
+                constants: vec![Object::Int(1103)],
+            };
+            assert_eq!(t.1, decode(&encode(&bytecode)).unwrap_err().message);
+        }
+    }
+}
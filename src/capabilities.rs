@@ -0,0 +1,123 @@
+//! The I/O a host grants to scripts. Builtins that need something not
+//! granted here fail with `ErrorKind::PermissionDenied`.
+
+use evaluator::EvalError;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where `puts` writes, where `read_line` reads, and which directories
+/// `read_file` and `write_file` may touch. The default grants nothing.
+#[derive(Default)]
+pub struct Capabilities {
+    pub output: Option<Box<dyn io::Write>>,
+    pub input: Option<Box<dyn io::BufRead>>,
+    pub fs_roots: Vec<PathBuf>,
+}
+
+impl Capabilities {
+    pub fn new() -> Capabilities {
+        Capabilities::default()
+    }
+
+    pub fn set_output(&mut self, output: Box<dyn io::Write>) {
+        self.output = Some(output);
+    }
+
+    pub fn set_input(&mut self, input: Box<dyn io::BufRead>) {
+        self.input = Some(input);
+    }
+
+    /// Lets scripts read and write files anywhere below `root`.
+    pub fn allow_fs_root<P: Into<PathBuf>>(&mut self, root: P) {
+        self.fs_roots.push(root.into());
+    }
+}
+
+impl fmt::Debug for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Capabilities")
+            .field("output", &self.output.is_some())
+            .field("input", &self.input.is_some())
+            .field("fs_roots", &self.fs_roots)
+            .finish()
+    }
+}
+
+/// Resolves `path` and checks that it lies below one of `roots`. Symlinks
+/// and `..` are resolved first, so neither escapes a root. A file that does
+/// not exist yet is checked by its directory, and must not be a dangling
+/// symlink, which writing would follow.
+pub fn check_path(roots: &[PathBuf], path: &str) -> Result<PathBuf, EvalError> {
+    if roots.is_empty() {
+        return Err(EvalError::permission_denied(
+            "no filesystem access was granted".to_string(),
+        ));
+    }
+    let path = Path::new(path);
+    let io_error = |e: io::Error| EvalError::new(format!("{}: {}", path.display(), e));
+    let resolved = match path.canonicalize() {
+        Ok(resolved) => resolved,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            if fs::symlink_metadata(path).is_ok() {
+                return Err(EvalError::permission_denied(format!(
+                    "{} is a symlink to a missing file",
+                    path.display()
+                )));
+            }
+            let name = path.file_name().ok_or_else(|| io_error(e))?;
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            dir.canonicalize().map_err(io_error)?.join(name)
+        }
+        Err(e) => return Err(io_error(e)),
+    };
+    let allowed = roots
+        .iter()
+        .filter_map(|root| root.canonicalize().ok())
+        .any(|root| resolved.starts_with(root));
+    if !allowed {
+        return Err(EvalError::permission_denied(format!(
+            "{} is outside the allowed directories",
+            path.display()
+        )));
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evaluator::ErrorKind;
+    use std::env;
+
+    #[test]
+    fn paths_stay_below_roots() {
+        let root = env::temp_dir().join(format!("monkey-capabilities-{}", std::process::id()));
+        fs::create_dir_all(root.join("inner")).unwrap();
+        let roots = vec![root.join("inner")];
+        let path = |p: &str| root.join(p).display().to_string();
+
+        assert!(check_path(&roots, &path("inner/new.txt")).is_ok());
+        for denied in ["outside.txt", "inner/../outside.txt"] {
+            let err = check_path(&roots, &path(denied)).unwrap_err();
+            assert_eq!(ErrorKind::PermissionDenied, err.kind);
+        }
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(root.join("escape.txt"), root.join("inner/link")).unwrap();
+            let err = check_path(&roots, &path("inner/link")).unwrap_err();
+            assert_eq!(ErrorKind::PermissionDenied, err.kind);
+            assert!(err.message.ends_with("is a symlink to a missing file"));
+        }
+        let err = check_path(&[], &path("inner/new.txt")).unwrap_err();
+        assert_eq!(
+            "permission denied: no filesystem access was granted",
+            err.message
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+}
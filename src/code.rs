@@ -0,0 +1,199 @@
+use std::convert::TryFrom;
+
+pub type Instructions = Vec<u8>;
+
+#[repr(u8)]
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Opcode {
+    Constant,
+    Pop,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Minus,
+    SetGlobal,
+    GetGlobal,
+    GetBuiltin,
+    Call,
+    Member,
+}
+
+/// The name of an opcode and the width in bytes of each of its operands.
+#[derive(Debug)]
+pub struct Definition {
+    pub name: &'static str,
+    pub operand_widths: &'static [usize],
+}
+
+impl Opcode {
+    pub fn definition(self) -> Definition {
+        let (name, operand_widths): (&'static str, &'static [usize]) = match self {
+            Opcode::Constant => ("OpConstant", &[2]),
+            Opcode::Pop => ("OpPop", &[]),
+            Opcode::Add => ("OpAdd", &[]),
+            Opcode::Sub => ("OpSub", &[]),
+            Opcode::Mul => ("OpMul", &[]),
+            Opcode::Div => ("OpDiv", &[]),
+            Opcode::Minus => ("OpMinus", &[]),
+            Opcode::SetGlobal => ("OpSetGlobal", &[2]),
+            Opcode::GetGlobal => ("OpGetGlobal", &[2]),
+            Opcode::GetBuiltin => ("OpGetBuiltin", &[1]),
+            Opcode::Call => ("OpCall", &[1]),
+            Opcode::Member => ("OpMember", &[]),
+        };
+        Definition {
+            name,
+            operand_widths,
+        }
+    }
+}
+
+impl TryFrom<u8> for Opcode {
+    type Error = String;
+
+    fn try_from(byte: u8) -> Result<Opcode, String> {
+        match byte {
+            0 => Ok(Opcode::Constant),
+            1 => Ok(Opcode::Pop),
+            2 => Ok(Opcode::Add),
+            3 => Ok(Opcode::Sub),
+            4 => Ok(Opcode::Mul),
+            5 => Ok(Opcode::Div),
+            6 => Ok(Opcode::Minus),
+            7 => Ok(Opcode::SetGlobal),
+            8 => Ok(Opcode::GetGlobal),
+            9 => Ok(Opcode::GetBuiltin),
+            10 => Ok(Opcode::Call),
+            11 => Ok(Opcode::Member),
+            _ => Err(format!("opcode {} undefined", byte)),
+        }
+    }
+}
+
+/// Encodes one instruction. Operands are written big-endian with the widths
+/// from the opcode's definition.
+pub fn make(op: Opcode, operands: &[usize]) -> Instructions {
+    let def = op.definition();
+    let mut instruction = vec![op as u8];
+    for (operand, width) in operands.iter().zip(def.operand_widths) {
+        match width {
+            1 => instruction.push(*operand as u8),
+            2 => instruction.extend_from_slice(&(*operand as u16).to_be_bytes()),
+            _ => unreachable!("operand width {}", width),
+        }
+    }
+    instruction
+}
+
+/// Decodes the operands that follow an opcode, returning them together with
+/// the number of bytes read.
+pub fn read_operands(def: &Definition, ins: &[u8]) -> (Vec<usize>, usize) {
+    let mut operands = Vec::with_capacity(def.operand_widths.len());
+    let mut offset = 0;
+    for width in def.operand_widths {
+        match width {
+            1 => operands.push(ins[offset] as usize),
+            2 => operands.push(read_u16(&ins[offset..]) as usize),
+            _ => unreachable!("operand width {}", width),
+        }
+        offset += width;
+    }
+    (operands, offset)
+}
+
+pub fn read_u16(ins: &[u8]) -> u16 {
+    u16::from_be_bytes([ins[0], ins[1]])
+}
+
+/// Formats the instruction at `offset` as `OpName operand...`, returning the
+/// text and the instruction's length in bytes.
+pub fn format_instruction(ins: &[u8], offset: usize) -> (String, usize) {
+    match Opcode::try_from(ins[offset]) {
+        Ok(op) => {
+            let def = op.definition();
+            let (operands, read) = read_operands(&def, &ins[offset + 1..]);
+            let mut text = def.name.to_string();
+            for operand in operands {
+                text.push_str(&format!(" {}", operand));
+            }
+            (text, 1 + read)
+        }
+        Err(e) => (format!("ERROR: {}", e), 1),
+    }
+}
+
+/// Renders instructions one per line, each prefixed with its offset.
+pub fn disassemble(ins: &[u8]) -> String {
+    let mut out = String::new();
+    let mut offset = 0;
+    while offset < ins.len() {
+        let (text, len) = format_instruction(ins, offset);
+        out.push_str(&format!("{:04} {}\n", offset, text));
+        offset += len;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn make_instruction() {
+        let tests = vec![
+            (
+                Opcode::Constant,
+                vec![65534],
+                vec![Opcode::Constant as u8, 255, 254],
+            ),
+            (Opcode::Add, vec![], vec![Opcode::Add as u8]),
+            (Opcode::Call, vec![255], vec![Opcode::Call as u8, 255]),
+            (
+                Opcode::GetGlobal,
+                vec![1103],
+                vec![Opcode::GetGlobal as u8, 4, 79],
+            ),
+        ];
+
+        for t in tests {
+            assert_eq!(t.2, make(t.0, &t.1));
+        }
+    }
+
+    #[test]
+    fn read_instruction_operands() {
+        let tests = vec![
+            (Opcode::Constant, vec![65535], 2),
+            (Opcode::GetBuiltin, vec![255], 1),
+            (Opcode::Pop, vec![], 0),
+        ];
+
+        for t in tests {
+            let instruction = make(t.0, &t.1);
+            let def = Opcode::try_from(instruction[0]).unwrap().definition();
+
+            let (operands, read) = read_operands(&def, &instruction[1..]);
+            assert_eq!(t.2, read);
+            assert_eq!(t.1, operands);
+        }
+    }
+
+    #[test]
+    fn disassemble_instructions() {
+        let instructions = [
+            make(Opcode::Add, &[]),
+            make(Opcode::Constant, &[2]),
+            make(Opcode::Constant, &[65535]),
+            vec![255],
+        ]
+        .concat();
+
+        let expected = "0000 OpAdd
+0001 OpConstant 2
+0004 OpConstant 65535
+0007 ERROR: opcode 255 undefined
+";
+        assert_eq!(expected, disassemble(&instructions));
+    }
+}
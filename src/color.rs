@@ -0,0 +1,31 @@
+use std::env;
+use std::io::IsTerminal;
+
+/// An ANSI SGR escape sequence.
+pub type Style = &'static str;
+
+pub const RESET: Style = "\x1b[0m";
+pub const BOLD: Style = "\x1b[1m";
+pub const ERROR: Style = "\x1b[1;31m";
+pub const HINT: Style = "\x1b[1;36m";
+pub const GUTTER: Style = "\x1b[1;34m";
+pub const KEYWORD: Style = "\x1b[35m";
+pub const LITERAL: Style = "\x1b[33m";
+pub const OPERATOR: Style = "\x1b[36m";
+
+/// Reports whether colored output should be written to `stream`, such as
+/// `io::stderr()` for diagnostics: it must be a terminal and `NO_COLOR`
+/// must be unset or empty.
+pub fn enabled_for<S: IsTerminal>(stream: &S) -> bool {
+    let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    !no_color && stream.is_terminal()
+}
+
+/// Wraps `text` in `style` if `color` is set.
+pub fn paint(text: &str, style: Style, color: bool) -> String {
+    if color {
+        format!("{}{}{}", style, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
@@ -0,0 +1,468 @@
+use ast::*;
+use builtins::Builtins;
+use code::{disassemble, make, Instructions, Opcode};
+use object::{HashKey, Object};
+use std::collections::HashMap;
+use symbol_table::{SymbolScope, SymbolTable};
+use token::{Span, TokenKind};
+
+#[derive(Debug)]
+pub struct CompileError {
+    pub message: String,
+    /// The expression that failed to compile, if known.
+    pub span: Option<Span>,
+}
+
+pub type CompileResult = Result<(), CompileError>;
+
+/// The output of the compiler: the instruction stream and the constant pool
+/// its `OpConstant` operands index into.
+#[derive(Debug, Clone)]
+pub struct Bytecode {
+    pub instructions: Instructions,
+    pub constants: Vec<Object>,
+}
+
+impl Bytecode {
+    /// Renders the instructions and the constant pool in a readable form.
+    pub fn disassemble(&self) -> String {
+        let mut out = String::from("== instructions ==\n");
+        out.push_str(&disassemble(&self.instructions));
+        out.push_str("== constants ==\n");
+        for (i, constant) in self.constants.iter().enumerate() {
+            out.push_str(&format!(
+                "{:04} {} {}\n",
+                i,
+                constant.type_name(),
+                constant.inspect()
+            ));
+        }
+        out
+    }
+}
+
+/// The parts of a compiler that carry over between compilations, so that
+/// later REPL inputs see earlier bindings and constants.
+#[derive(Debug, Default, Clone)]
+pub struct CompilerState {
+    pub symbol_table: SymbolTable,
+    pub constants: Vec<Object>,
+}
+
+impl CompilerState {
+    /// Returns a state in which `builtins` resolve by name.
+    pub fn with_builtins(builtins: &Builtins) -> CompilerState {
+        let mut state = CompilerState::default();
+        for (index, builtin) in builtins.iter().enumerate() {
+            state.symbol_table.define_builtin(index, &builtin.name);
+        }
+        state
+    }
+}
+
+/// Lowers an `ast::Program` to stack machine bytecode.
+#[derive(Debug, Default)]
+pub struct Compiler {
+    instructions: Instructions,
+    constants: Vec<Object>,
+    constant_indices: HashMap<HashKey, usize>,
+    symbol_table: SymbolTable,
+}
+
+impl Compiler {
+    pub fn new() -> Compiler {
+        Compiler::default()
+    }
+
+    pub fn new_with_state(state: CompilerState) -> Compiler {
+        let constant_indices = state
+            .constants
+            .iter()
+            .enumerate()
+            .filter_map(|(i, c)| c.hash_key().map(|k| (k, i)))
+            .collect();
+        Compiler {
+            instructions: Instructions::new(),
+            constants: state.constants,
+            constant_indices,
+            symbol_table: state.symbol_table,
+        }
+    }
+
+    pub fn into_state(self) -> CompilerState {
+        CompilerState {
+            symbol_table: self.symbol_table,
+            constants: self.constants,
+        }
+    }
+
+    pub fn compile(&mut self, node: &Node) -> CompileResult {
+        match node {
+            Node::Program(prog) => self.compile_program(prog),
+            Node::Statement(stmt) => self.compile_statement(stmt),
+            Node::Expression(exp) => self.compile_expression(exp),
+        }
+    }
+
+    pub fn bytecode(&self) -> Bytecode {
+        Bytecode {
+            instructions: self.instructions.clone(),
+            constants: self.constants.clone(),
+        }
+    }
+
+    fn compile_program(&mut self, prog: &Program) -> CompileResult {
+        for stmt in &prog.statements {
+            self.compile_statement(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn compile_statement(&mut self, stmt: &Statement) -> CompileResult {
+        match stmt {
+            Statement::Expression(exp) => {
+                self.compile_expression(&exp.expression)?;
+                self.emit(Opcode::Pop, &[])?;
+            }
+            Statement::Let(stmt) => {
+                self.compile_expression(&stmt.value)?;
+                let symbol = self.symbol_table.define(&stmt.name);
+                self.emit(Opcode::SetGlobal, &[symbol.index])?;
+            }
+            // The session bound the module before compiling the program, so
+            // the statement only yields it.
+            Statement::Import(stmt) => match self.symbol_table.resolve(&stmt.alias) {
+                Some(ref symbol) if symbol.scope == SymbolScope::Global => {
+                    self.emit(Opcode::GetGlobal, &[symbol.index])?;
+                    self.emit(Opcode::Pop, &[])?;
+                }
+                _ => {
+                    return Err(CompileError {
+                        message: format!("module not loaded: {}", stmt.path),
+                        span: Some(stmt.span),
+                    })
+                }
+            },
+        }
+        Ok(())
+    }
+
+    // Each kind of expression compiles in a function of its own so the
+    // recursion through `compile_expression` keeps a small stack frame.
+    fn compile_expression(&mut self, exp: &Expression) -> CompileResult {
+        match exp {
+            Expression::Identifier(ident) => self.compile_identifier(ident),
+            Expression::Integer(int) => {
+                let index = self.add_constant(Object::Int(int.value));
+                self.emit(Opcode::Constant, &[index]).map(|_| ())
+            }
+            Expression::Prefix(expr) => self.compile_prefix_expression(expr),
+            Expression::Infix(expr) => self.compile_infix_expression(expr),
+            Expression::Call(expr) => self.compile_call_expression(expr),
+            Expression::Member(expr) => self.compile_member_expression(expr),
+        }
+    }
+
+    fn compile_identifier(&mut self, ident: &Identifier) -> CompileResult {
+        match self.symbol_table.resolve(&ident.name) {
+            Some(ref symbol) if symbol.scope == SymbolScope::Global => {
+                self.emit(Opcode::GetGlobal, &[symbol.index])?;
+            }
+            Some(ref symbol) if symbol.scope == SymbolScope::Builtin => {
+                self.emit(Opcode::GetBuiltin, &[symbol.index])?;
+            }
+            _ => {
+                return Err(CompileError {
+                    message: format!("identifier not found: {}", ident.name),
+                    span: Some(ident.span),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_prefix_expression(&mut self, expr: &PrefixExpression) -> CompileResult {
+        self.compile_expression(&expr.right)?;
+        match expr.operator {
+            TokenKind::Minus => self.emit(Opcode::Minus, &[])?,
+            _ => {
+                return Err(CompileError {
+                    message: format!("unknown operator {}", expr.operator),
+                    span: Some(expr.span),
+                })
+            }
+        };
+        Ok(())
+    }
+
+    fn compile_infix_expression(&mut self, expr: &InfixExpression) -> CompileResult {
+        self.compile_expression(&expr.left)?;
+        self.compile_expression(&expr.right)?;
+        match expr.operator {
+            TokenKind::Plus => self.emit(Opcode::Add, &[])?,
+            TokenKind::Minus => self.emit(Opcode::Sub, &[])?,
+            TokenKind::Asterisk => self.emit(Opcode::Mul, &[])?,
+            TokenKind::Slash => self.emit(Opcode::Div, &[])?,
+            _ => {
+                return Err(CompileError {
+                    message: format!("unknown operator {}", expr.operator),
+                    span: Some(expr.span),
+                })
+            }
+        };
+        Ok(())
+    }
+
+    fn compile_call_expression(&mut self, expr: &CallExpression) -> CompileResult {
+        if expr.arguments.len() > u8::MAX as usize {
+            return Err(CompileError {
+                message: format!(
+                    "too many arguments: {}, at most {}",
+                    expr.arguments.len(),
+                    u8::MAX
+                ),
+                span: Some(expr.span),
+            });
+        }
+        self.compile_expression(&expr.function)?;
+        for arg in &expr.arguments {
+            self.compile_expression(arg)?;
+        }
+        self.emit(Opcode::Call, &[expr.arguments.len()])?;
+        Ok(())
+    }
+
+    fn compile_member_expression(&mut self, expr: &MemberExpression) -> CompileResult {
+        self.compile_expression(&expr.object)?;
+        let index = self.add_constant(Object::Str(expr.name.clone()));
+        self.emit(Opcode::Constant, &[index])?;
+        self.emit(Opcode::Member, &[])?;
+        Ok(())
+    }
+
+    /// Returns the index of `obj` in the constant pool, adding it if no
+    /// equal constant is there yet.
+    fn add_constant(&mut self, obj: Object) -> usize {
+        let key = obj.hash_key();
+        if let Some(index) = key.as_ref().and_then(|k| self.constant_indices.get(k)) {
+            return *index;
+        }
+        self.constants.push(obj);
+        let index = self.constants.len() - 1;
+        if let Some(key) = key {
+            self.constant_indices.insert(key, index);
+        }
+        index
+    }
+
+    /// Appends an instruction and returns its position. Fails if an operand
+    /// does not fit its width, e.g. once there are more than 65536
+    /// constants or globals.
+    fn emit(&mut self, op: Opcode, operands: &[usize]) -> Result<usize, CompileError> {
+        let def = op.definition();
+        for (operand, width) in operands.iter().zip(def.operand_widths) {
+            let max = (1usize << (8 * width)) - 1;
+            if *operand > max {
+                return Err(CompileError {
+                    message: format!(
+                        "operand {} of {} is too large, at most {}",
+                        operand, def.name, max
+                    ),
+                    span: None,
+                });
+            }
+        }
+        let position = self.instructions.len();
+        self.instructions.extend(make(op, operands));
+        Ok(position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser;
+
+    fn compile(input: &str) -> Bytecode {
+        let node = parser::parse(input).expect(input);
+        let mut compiler = Compiler::new();
+        compiler.compile(&node).expect(input);
+        compiler.bytecode()
+    }
+
+    #[test]
+    fn compile_integer_arithmetic() {
+        let tests = vec![
+            (
+                "1103 + 2206;",
+                vec![1103, 2206],
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::Add, &[]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+            (
+                "1; 2;",
+                vec![1, 2],
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Pop, &[]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+            (
+                "(-1103) * (2 - 1) / 1;",
+                vec![1103, 2, 1],
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Minus, &[]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::Constant, &[2]),
+                    make(Opcode::Sub, &[]),
+                    make(Opcode::Mul, &[]),
+                    make(Opcode::Constant, &[2]),
+                    make(Opcode::Div, &[]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+        ];
+
+        for t in tests {
+            let bytecode = compile(t.0);
+            assert_eq!(t.2.concat(), bytecode.instructions, "{}", t.0);
+            let constants: Vec<i64> = bytecode
+                .constants
+                .iter()
+                .map(|c| match c {
+                    Object::Int(i) => *i,
+                    obj => panic!("expected integer constant but got {:?}", obj),
+                })
+                .collect();
+            assert_eq!(t.1, constants, "{}", t.0);
+        }
+    }
+
+    #[test]
+    fn compile_global_let_statements() {
+        let bytecode = compile("let one = 1; let two = one; two;");
+
+        let expected = [
+            make(Opcode::Constant, &[0]),
+            make(Opcode::SetGlobal, &[0]),
+            make(Opcode::GetGlobal, &[0]),
+            make(Opcode::SetGlobal, &[1]),
+            make(Opcode::GetGlobal, &[1]),
+            make(Opcode::Pop, &[]),
+        ];
+        assert_eq!(expected.concat(), bytecode.instructions);
+    }
+
+    #[test]
+    fn compile_builtin_calls() {
+        let node = parser::parse("let a = 1; len(a, 2); len;").unwrap();
+        let mut state = CompilerState::default();
+        state.symbol_table.define_builtin(3, "len");
+        let mut compiler = Compiler::new_with_state(state);
+        compiler.compile(&node).unwrap();
+
+        let expected = [
+            make(Opcode::Constant, &[0]),
+            make(Opcode::SetGlobal, &[0]),
+            make(Opcode::GetBuiltin, &[3]),
+            make(Opcode::GetGlobal, &[0]),
+            make(Opcode::Constant, &[1]),
+            make(Opcode::Call, &[2]),
+            make(Opcode::Pop, &[]),
+            make(Opcode::GetBuiltin, &[3]),
+            make(Opcode::Pop, &[]),
+        ];
+        assert_eq!(expected.concat(), compiler.bytecode().instructions);
+
+        let mut state = CompilerState::default();
+        state.symbol_table.define_builtin(256, "len");
+        let mut compiler = Compiler::new_with_state(state);
+        let err = compiler
+            .compile(&parser::parse("len;").unwrap())
+            .unwrap_err();
+        assert_eq!(
+            "operand 256 of OpGetBuiltin is too large, at most 255",
+            err.message
+        );
+    }
+
+    #[test]
+    fn disassemble_bytecode() {
+        let expected = "== instructions ==
+0000 OpConstant 0
+0003 OpSetGlobal 0
+0006 OpGetGlobal 0
+0009 OpConstant 1
+0012 OpMul
+0013 OpPop
+== constants ==
+0000 INTEGER 1103
+0001 INTEGER 2
+";
+        assert_eq!(expected, compile("let a = 1103; a * 2;").disassemble());
+    }
+
+    #[test]
+    fn compile_unknown_identifier() {
+        let node = parser::parse("birthday;").unwrap();
+        let err = Compiler::new().compile(&node).unwrap_err();
+        assert_eq!("identifier not found: birthday", err.message);
+    }
+
+    #[test]
+    fn reuse_constants_across_inputs() {
+        let mut compiler = Compiler::new();
+        compiler
+            .compile(&parser::parse("1103; 2206;").unwrap())
+            .unwrap();
+        let mut compiler = Compiler::new_with_state(compiler.into_state());
+        compiler
+            .compile(&parser::parse("2206 + 1103;").unwrap())
+            .unwrap();
+
+        let expected = [
+            make(Opcode::Constant, &[1]),
+            make(Opcode::Constant, &[0]),
+            make(Opcode::Add, &[]),
+            make(Opcode::Pop, &[]),
+        ];
+        assert_eq!(expected.concat(), compiler.bytecode().instructions);
+        assert_eq!(2, compiler.bytecode().constants.len());
+    }
+
+    #[test]
+    fn reject_operand_overflow() {
+        let state = CompilerState {
+            constants: (0..65536).map(Object::Int).collect(),
+            ..Default::default()
+        };
+        let mut compiler = Compiler::new_with_state(state);
+        let err = compiler
+            .compile(&parser::parse("65535; 65536;").unwrap())
+            .unwrap_err();
+        assert_eq!(
+            "operand 65536 of OpConstant is too large, at most 65535",
+            err.message
+        );
+
+        let mut state = CompilerState::default();
+        for i in 0..65536 {
+            state.symbol_table.define(&format!("g{}", i));
+        }
+        let mut compiler = Compiler::new_with_state(state);
+        let err = compiler
+            .compile(&parser::parse("let a = 1;").unwrap())
+            .unwrap_err();
+        assert_eq!(
+            "operand 65536 of OpSetGlobal is too large, at most 65535",
+            err.message
+        );
+    }
+}
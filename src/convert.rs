@@ -0,0 +1,364 @@
+use builtins::BuiltinFunction;
+use evaluator::{EvalError, EvalResult};
+use object::{HashKey, Object};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hash};
+use std::rc::Rc;
+
+/// Converts a Rust value into a Monkey object.
+pub trait IntoObject {
+    fn into_object(self) -> Object;
+}
+
+/// Converts a Monkey object into a Rust value, describing the mismatch when
+/// the object has the wrong type.
+pub trait FromObject: Sized {
+    fn from_object(obj: &Object) -> Result<Self, String>;
+}
+
+fn mismatch<T>(expected: &str, obj: &Object) -> Result<T, String> {
+    Err(format!("expected {}, got {}", expected, obj.type_name()))
+}
+
+impl IntoObject for Object {
+    fn into_object(self) -> Object {
+        self
+    }
+}
+
+impl FromObject for Object {
+    fn from_object(obj: &Object) -> Result<Object, String> {
+        Ok(obj.clone())
+    }
+}
+
+impl IntoObject for () {
+    fn into_object(self) -> Object {
+        Object::Null
+    }
+}
+
+impl IntoObject for i64 {
+    fn into_object(self) -> Object {
+        Object::Int(self)
+    }
+}
+
+impl FromObject for i64 {
+    fn from_object(obj: &Object) -> Result<i64, String> {
+        match obj {
+            Object::Int(i) => Ok(*i),
+            obj => mismatch("INTEGER", obj),
+        }
+    }
+}
+
+impl IntoObject for bool {
+    fn into_object(self) -> Object {
+        Object::Boolean(self)
+    }
+}
+
+impl FromObject for bool {
+    fn from_object(obj: &Object) -> Result<bool, String> {
+        match obj {
+            Object::Boolean(b) => Ok(*b),
+            obj => mismatch("BOOLEAN", obj),
+        }
+    }
+}
+
+impl IntoObject for String {
+    fn into_object(self) -> Object {
+        Object::Str(self)
+    }
+}
+
+impl IntoObject for &str {
+    fn into_object(self) -> Object {
+        Object::Str(self.to_string())
+    }
+}
+
+impl FromObject for String {
+    fn from_object(obj: &Object) -> Result<String, String> {
+        match obj {
+            Object::Str(s) => Ok(s.clone()),
+            obj => mismatch("STRING", obj),
+        }
+    }
+}
+
+impl<T: IntoObject> IntoObject for Option<T> {
+    fn into_object(self) -> Object {
+        match self {
+            Some(value) => value.into_object(),
+            None => Object::Null,
+        }
+    }
+}
+
+impl<T: FromObject> FromObject for Option<T> {
+    fn from_object(obj: &Object) -> Result<Option<T>, String> {
+        match obj {
+            Object::Null => Ok(None),
+            obj => T::from_object(obj).map(Some),
+        }
+    }
+}
+
+impl<T: IntoObject> IntoObject for Vec<T> {
+    fn into_object(self) -> Object {
+        Object::Array(self.into_iter().map(IntoObject::into_object).collect())
+    }
+}
+
+impl<T: FromObject> FromObject for Vec<T> {
+    fn from_object(obj: &Object) -> Result<Vec<T>, String> {
+        match obj {
+            Object::Array(elements) => elements
+                .iter()
+                .enumerate()
+                .map(|(i, e)| T::from_object(e).map_err(|m| format!("element {}: {}", i, m)))
+                .collect(),
+            obj => mismatch("ARRAY", obj),
+        }
+    }
+}
+
+impl From<i64> for HashKey {
+    fn from(i: i64) -> HashKey {
+        HashKey::Int(i)
+    }
+}
+
+impl From<bool> for HashKey {
+    fn from(b: bool) -> HashKey {
+        HashKey::Boolean(b)
+    }
+}
+
+impl From<String> for HashKey {
+    fn from(s: String) -> HashKey {
+        HashKey::Str(s)
+    }
+}
+
+impl From<&str> for HashKey {
+    fn from(s: &str) -> HashKey {
+        HashKey::Str(s.to_string())
+    }
+}
+
+impl<K, V, S> IntoObject for HashMap<K, V, S>
+where
+    K: Into<HashKey>,
+    V: IntoObject,
+{
+    fn into_object(self) -> Object {
+        let pairs: BTreeMap<HashKey, Object> = self
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into_object()))
+            .collect();
+        Object::Hash(pairs)
+    }
+}
+
+impl<K, V, S> FromObject for HashMap<K, V, S>
+where
+    K: FromObject + Eq + Hash,
+    V: FromObject,
+    S: BuildHasher + Default,
+{
+    fn from_object(obj: &Object) -> Result<HashMap<K, V, S>, String> {
+        match obj {
+            Object::Hash(pairs) => pairs
+                .iter()
+                .map(|(k, v)| {
+                    let key = Object::from(k.clone());
+                    let context = |m| format!("key {}: {}", key.inspect(), m);
+                    let k = K::from_object(&key).map_err(context)?;
+                    let v = V::from_object(v).map_err(context)?;
+                    Ok((k, v))
+                })
+                .collect(),
+            obj => mismatch("HASH", obj),
+        }
+    }
+}
+
+macro_rules! impl_tuple {
+    ($len:expr, $($name:ident $index:tt),+) => {
+        impl<$($name: IntoObject),+> IntoObject for ($($name,)+) {
+            fn into_object(self) -> Object {
+                Object::Array(vec![$(self.$index.into_object()),+])
+            }
+        }
+
+        impl<$($name: FromObject),+> FromObject for ($($name,)+) {
+            fn from_object(obj: &Object) -> Result<($($name,)+), String> {
+                match obj {
+                    Object::Array(elements) if elements.len() == $len => Ok(($(
+                        $name::from_object(&elements[$index])
+                            .map_err(|m| format!("element {}: {}", $index, m))?,
+                    )+)),
+                    Object::Array(elements) => Err(format!(
+                        "expected an array of {} elements, got {}",
+                        $len,
+                        elements.len()
+                    )),
+                    obj => mismatch("ARRAY", obj),
+                }
+            }
+        }
+    };
+}
+
+impl_tuple!(1, A 0);
+impl_tuple!(2, A 0, B 1);
+impl_tuple!(3, A 0, B 1, C 2);
+impl_tuple!(4, A 0, B 1, C 2, D 3);
+
+/// What a native function may return: a value, or a value or an error.
+pub trait IntoEvalResult {
+    fn into_eval_result(self) -> EvalResult;
+}
+
+impl<T: IntoObject> IntoEvalResult for T {
+    fn into_eval_result(self) -> EvalResult {
+        Ok(self.into_object())
+    }
+}
+
+impl<T: IntoObject> IntoEvalResult for Result<T, EvalError> {
+    fn into_eval_result(self) -> EvalResult {
+        self.map(IntoObject::into_object)
+    }
+}
+
+/// Converts argument `index` of a call to `name`, naming its position if it
+/// has the wrong type.
+pub fn argument<T: FromObject>(name: &str, args: &[Object], index: usize) -> Result<T, EvalError> {
+    T::from_object(&args[index])
+        .map_err(|m| EvalError::new(format!("argument {} to {}: {}", index + 1, name, m)))
+}
+
+/// A Rust function with typed parameters that can be called as a builtin.
+/// `Args` is the tuple of its parameter types.
+pub trait NativeFunction<Args> {
+    fn arity() -> usize;
+    fn into_builtin_function(self, name: &str) -> BuiltinFunction;
+}
+
+macro_rules! impl_native_function {
+    ($($name:ident $index:tt),*) => {
+        impl<Func, Ret, $($name),*> NativeFunction<($($name,)*)> for Func
+        where
+            Func: Fn($($name),*) -> Ret + 'static,
+            Ret: IntoEvalResult,
+            $($name: FromObject,)*
+        {
+            fn arity() -> usize {
+                <[&str]>::len(&[$(stringify!($name)),*])
+            }
+
+            #[allow(unused_variables)]
+            fn into_builtin_function(self, name: &str) -> BuiltinFunction {
+                let name = name.to_string();
+                Rc::new(move |args: &[Object]| {
+                    self($(argument::<$name>(&name, args, $index)?),*).into_eval_result()
+                })
+            }
+        }
+    };
+}
+
+impl_native_function!();
+impl_native_function!(A 0);
+impl_native_function!(A 0, B 1);
+impl_native_function!(A 0, B 1, C 2);
+impl_native_function!(A 0, B 1, C 2, D 3);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use builtins::Builtins;
+
+    fn round_trip<T: IntoObject + FromObject>(value: T) -> T {
+        T::from_object(&value.into_object()).unwrap()
+    }
+
+    #[test]
+    fn convert_values() {
+        assert_eq!(1103, round_trip(1103i64));
+        assert!(round_trip(true));
+        assert_eq!("monkey", round_trip("monkey".to_string()));
+        assert_eq!(Some(1), round_trip(Some(1i64)));
+        assert_eq!(None, round_trip(None::<i64>));
+        assert_eq!(vec![1, 2], round_trip(vec![1i64, 2]));
+        assert_eq!((1, "a".to_string()), round_trip((1i64, "a".to_string())));
+
+        let mut map = HashMap::new();
+        map.insert("b".to_string(), vec![2i64]);
+        map.insert("a".to_string(), vec![]);
+        assert_eq!("{a: [], b: [2]}", map.clone().into_object().inspect());
+        assert_eq!(map, round_trip(map.clone()));
+
+        assert_eq!("null", ().into_object().inspect());
+        assert_eq!("[1, true, x]", (1i64, true, "x").into_object().inspect());
+    }
+
+    #[test]
+    fn conversion_errors() {
+        let tests = vec![
+            (
+                i64::from_object(&Object::Boolean(true)),
+                "expected INTEGER, got BOOLEAN",
+            ),
+            (
+                Vec::<i64>::from_object(&vec![Object::Int(1), Object::Null].into_object())
+                    .map(|_| 0),
+                "element 1: expected INTEGER, got NULL",
+            ),
+            (
+                <(i64, i64)>::from_object(&vec![1i64].into_object()).map(|_| 0),
+                "expected an array of 2 elements, got 1",
+            ),
+        ];
+
+        for t in tests {
+            assert_eq!(t.1, t.0.unwrap_err());
+        }
+    }
+
+    #[test]
+    fn native_functions() {
+        let mut builtins = Builtins::new();
+        builtins.register_fn("repeat", "Repeats a string.", |s: String, n: i64| {
+            s.repeat(n as usize)
+        });
+        builtins.register_fn("checked_div", "", |a: i64, b: i64| {
+            a.checked_div(b)
+                .ok_or_else(|| EvalError::new("division by zero".to_string()))
+        });
+        builtins.register_fn("answer", "", || 42i64);
+
+        let repeat = builtins.get("repeat").unwrap();
+        assert_eq!(2, repeat.arity);
+        let args = ["ab".into_object(), 3i64.into_object()];
+        assert_eq!("ababab", repeat.call(&args).unwrap().inspect());
+        let err = repeat.call(&[Object::Int(1), Object::Int(2)]).unwrap_err();
+        assert_eq!(
+            "argument 1 to repeat: expected STRING, got INTEGER",
+            err.message
+        );
+
+        let div = builtins.get("checked_div").unwrap();
+        let err = div.call(&[Object::Int(1), Object::Int(0)]).unwrap_err();
+        assert_eq!("division by zero", err.message);
+
+        let answer = builtins.get("answer").unwrap();
+        assert_eq!(0, answer.arity);
+        assert_eq!("42", answer.call(&[]).unwrap().inspect());
+    }
+}
@@ -0,0 +1,179 @@
+use color;
+use compiler::CompileError;
+use evaluator::{ErrorKind, EvalError};
+use parser::Parser;
+use token::Span;
+use vm::VmError;
+
+/// An error report in the style of rustc: the message, the offending source
+/// line with the span underlined, and an optional hint.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub span: Option<Span>,
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    /// Builds a report for a runtime error without a location.
+    pub fn error(message: String) -> Diagnostic {
+        Diagnostic {
+            kind: ErrorKind::Runtime,
+            message,
+            span: None,
+            hint: None,
+        }
+    }
+
+    /// Builds the report for an error returned by `parser`. Its kind is
+    /// `StackOverflow` if the input nested too deeply, otherwise `Syntax`.
+    pub fn parse_error(message: String, parser: &Parser) -> Diagnostic {
+        let hint = if parser.at_eof() {
+            Some("the input ended early; is a `;` or a closing paren missing?".to_string())
+        } else {
+            None
+        };
+        let kind = if parser.overflowed() {
+            ErrorKind::StackOverflow
+        } else {
+            ErrorKind::Syntax
+        };
+        Diagnostic {
+            kind,
+            message,
+            span: Some(parser.span()),
+            hint,
+        }
+    }
+
+    pub fn render(&self, source: &str, color: bool) -> String {
+        let mut out = format!(
+            "{}{}",
+            color::paint("error", color::ERROR, color),
+            color::paint(&format!(": {}", self.message), color::BOLD, color)
+        );
+        let bar = color::paint("|", color::GUTTER, color);
+        let mut gutter = String::new();
+
+        if let Some(span) = self.span {
+            // An error at the end of the input points just past the last token.
+            let start = span.start.min(source.trim_end().len());
+            let end = span.end.max(start);
+            let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+            let line_end = source[start..]
+                .find('\n')
+                .map_or(source.len(), |i| start + i);
+            let line_no = (source[..start].matches('\n').count() + 1).to_string();
+            let column = source[line_start..start].chars().count();
+            let width = source[start..end.min(line_end)].chars().count().max(1);
+
+            gutter = " ".repeat(line_no.len());
+            out.push_str(&format!(
+                "\n{}{} {}:{}",
+                gutter,
+                color::paint("-->", color::GUTTER, color),
+                line_no,
+                column + 1
+            ));
+            out.push_str(&format!("\n{} {}", gutter, bar));
+            out.push_str(&format!(
+                "\n{} {} {}",
+                color::paint(&line_no, color::GUTTER, color),
+                bar,
+                &source[line_start..line_end]
+            ));
+            out.push_str(&format!(
+                "\n{} {} {}{}",
+                gutter,
+                bar,
+                " ".repeat(column),
+                color::paint(&"^".repeat(width), color::ERROR, color)
+            ));
+        }
+
+        if let Some(ref hint) = self.hint {
+            out.push_str(&format!(
+                "\n{} {} {}",
+                gutter,
+                color::paint("= hint:", color::HINT, color),
+                hint
+            ));
+        }
+        out
+    }
+}
+
+impl From<EvalError> for Diagnostic {
+    fn from(e: EvalError) -> Diagnostic {
+        Diagnostic {
+            kind: e.kind,
+            span: e.span,
+            ..Diagnostic::error(e.message)
+        }
+    }
+}
+
+impl From<CompileError> for Diagnostic {
+    fn from(e: CompileError) -> Diagnostic {
+        Diagnostic {
+            span: e.span,
+            ..Diagnostic::error(e.message)
+        }
+    }
+}
+
+impl From<VmError> for Diagnostic {
+    fn from(e: VmError) -> Diagnostic {
+        Diagnostic {
+            kind: e.kind,
+            ..Diagnostic::error(e.message)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lexer::Lexer;
+
+    fn parse_error(input: &str) -> String {
+        let mut p = Parser::new(Lexer::new(input));
+        let message = p.parse_program().unwrap_err();
+        Diagnostic::parse_error(message, &p).render(input, false)
+    }
+
+    #[test]
+    fn render_parse_error() {
+        assert_eq!(
+            "error: expect token Semicolon but 1103\n --> 2:6\n  |\n2 | 1103 1103;\n  |      ^^^^",
+            parse_error("1103;\n1103 1103;")
+        );
+    }
+
+    #[test]
+    fn render_parse_error_at_eof() {
+        assert_eq!(
+            "error: invalid token Eof\n --> 1:7\n  |\n1 | 1103 +\n  |       ^\n  = hint: the input ended early; is a `;` or a closing paren missing?",
+            parse_error("1103 +\n")
+        );
+    }
+
+    #[test]
+    fn render_without_span() {
+        let d = Diagnostic::error("identifier not found: birthday".to_string());
+        assert_eq!(
+            "error: identifier not found: birthday",
+            d.render("birthday;", false)
+        );
+    }
+
+    #[test]
+    fn render_with_color() {
+        let d = Diagnostic::error("boom".to_string());
+        assert_eq!(
+            "\x1b[1;31merror\x1b[0m\x1b[1m: boom\x1b[0m",
+            d.render("", true)
+        );
+    }
+}
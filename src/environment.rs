@@ -0,0 +1,45 @@
+use object::Object;
+use std::collections::HashMap;
+
+#[derive(Debug, Default)]
+pub struct Environment {
+    store: HashMap<String, Object>,
+    usage: usize,
+}
+
+impl Environment {
+    pub fn new() -> Environment {
+        Environment::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Object> {
+        self.store.get(name).cloned()
+    }
+
+    pub fn set(&mut self, name: String, value: Object) {
+        self.usage = self.usage_after_set(&name, &value);
+        self.store.insert(name, value);
+    }
+
+    /// Approximate bytes held by the bound values. The VM counts its globals
+    /// the same way, so both engines hit `Limits::max_memory` alike.
+    pub fn memory_usage(&self) -> usize {
+        self.usage
+    }
+
+    /// What `memory_usage` would return after binding `value` to `name`.
+    pub fn usage_after_set(&self, name: &str, value: &Object) -> usize {
+        let replaced = match self.store.get(name) {
+            Some(old) => old.size(),
+            None => 0,
+        };
+        self.usage - replaced + value.size()
+    }
+
+    /// Returns the bound names in sorted order.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.store.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
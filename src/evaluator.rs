@@ -1,104 +1,395 @@
 use ast::*;
-use object::Object;
-use token::Token;
+use builtins::Builtins;
+use environment::Environment;
+use object::{HashKey, Object};
+use std::time::{Duration, Instant};
+use token::{Span, TokenKind};
 
 pub type EvalResult = Result<Object, EvalError>;
 
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum ErrorKind {
+    /// The source did not parse.
+    Syntax,
+    /// An error in the program, such as an unbound name.
+    Runtime,
+    /// Evaluation nested deeper than `Limits::max_depth`.
+    StackOverflow,
+    /// Evaluation took more steps than `Limits::fuel` allows.
+    OutOfFuel,
+    /// Evaluation ran past `Limits::timeout`.
+    Timeout,
+    /// Bindings would grow past `Limits::max_memory`.
+    OutOfMemory,
+    /// A builtin needed a capability the host did not grant.
+    PermissionDenied,
+}
+
 #[derive(Debug)]
 pub struct EvalError {
+    pub kind: ErrorKind,
     pub message: String,
+    /// The expression that failed, if known.
+    pub span: Option<Span>,
 }
 
-pub fn eval(node: &Node) -> EvalResult {
+impl EvalError {
+    pub fn new(message: String) -> EvalError {
+        EvalError {
+            kind: ErrorKind::Runtime,
+            message,
+            span: None,
+        }
+    }
+
+    pub fn stack_overflow(limit: usize) -> EvalError {
+        EvalError {
+            kind: ErrorKind::StackOverflow,
+            message: format!("stack overflow: evaluation nested deeper than {}", limit),
+            span: None,
+        }
+    }
+
+    pub fn out_of_fuel(fuel: u64) -> EvalError {
+        EvalError {
+            kind: ErrorKind::OutOfFuel,
+            message: format!("out of fuel: evaluation took more than {} steps", fuel),
+            span: None,
+        }
+    }
+
+    pub fn timeout(timeout: Duration) -> EvalError {
+        EvalError {
+            kind: ErrorKind::Timeout,
+            message: format!("timeout: evaluation took longer than {:?}", timeout),
+            span: None,
+        }
+    }
+
+    pub fn permission_denied(message: String) -> EvalError {
+        EvalError {
+            kind: ErrorKind::PermissionDenied,
+            message: format!("permission denied: {}", message),
+            span: None,
+        }
+    }
+
+    pub fn out_of_memory(usage: usize, limit: usize) -> EvalError {
+        EvalError {
+            kind: ErrorKind::OutOfMemory,
+            message: format!(
+                "out of memory: bindings would use {} bytes, over the limit of {}",
+                usage, limit
+            ),
+            span: None,
+        }
+    }
+
+    /// Points the error at `span`, unless it already points at a
+    /// subexpression.
+    pub fn at(mut self, span: Span) -> EvalError {
+        self.span.get_or_insert(span);
+        self
+    }
+}
+
+/// Bounds on the resources one evaluation may use.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// How deeply expressions may nest while being evaluated. Deeper
+    /// evaluation fails with `ErrorKind::StackOverflow` instead of
+    /// overflowing the host's stack. Only the tree walker recurses, so the
+    /// VM ignores this; both engines rely on the parser's own limit, see
+    /// `Session::set_max_parse_depth`, to reject deeply nested source.
+    pub max_depth: usize,
+    /// How many steps one evaluation may take, if bounded. The tree walker
+    /// counts evaluated expressions, the VM executed instructions.
+    pub fuel: Option<u64>,
+    /// How long one evaluation may run, if bounded.
+    pub timeout: Option<Duration>,
+    /// How many bytes bound values may hold, if bounded. Usage is
+    /// approximate, see `Object::size`.
+    pub max_memory: Option<usize>,
+}
+
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_depth: DEFAULT_MAX_DEPTH,
+            fuel: None,
+            timeout: None,
+            max_memory: None,
+        }
+    }
+}
+
+/// How often a running evaluation looks at the clock, in steps.
+const CLOCK_INTERVAL: u64 = 256;
+
+/// Counts the steps of one evaluation, including the modules it imports,
+/// against its fuel and deadline. The clock is only read when a timeout is
+/// set.
+pub struct Budget {
+    fuel: Option<u64>,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    steps: u64,
+}
+
+impl Budget {
+    pub fn new(limits: &Limits) -> Budget {
+        Budget {
+            fuel: limits.fuel,
+            timeout: limits.timeout,
+            deadline: limits.timeout.map(|t| Instant::now() + t),
+            steps: 0,
+        }
+    }
+
+    /// Records one step, failing once the fuel or the time has run out.
+    pub fn step(&mut self) -> Result<(), EvalError> {
+        if let Some(fuel) = self.fuel {
+            if self.steps >= fuel {
+                return Err(EvalError::out_of_fuel(fuel));
+            }
+        }
+        if let (Some(deadline), Some(timeout)) = (self.deadline, self.timeout) {
+            if self.steps.is_multiple_of(CLOCK_INTERVAL) && Instant::now() >= deadline {
+                return Err(EvalError::timeout(timeout));
+            }
+        }
+        self.steps += 1;
+        Ok(())
+    }
+}
+
+/// Fails if memory use would grow to `usage` bytes, past `limits.max_memory`.
+pub fn check_memory(limits: &Limits, usage: usize) -> Result<(), EvalError> {
+    match limits.max_memory {
+        Some(limit) if usage > limit => Err(EvalError::out_of_memory(usage, limit)),
+        _ => Ok(()),
+    }
+}
+
+struct Context<'a> {
+    env: &'a mut Environment,
+    limits: &'a Limits,
+    builtins: &'a Builtins,
+    budget: &'a mut Budget,
+    depth: usize,
+}
+
+pub fn eval(node: &Node, env: &mut Environment) -> EvalResult {
+    eval_with_limits(node, env, &Limits::default())
+}
+
+pub fn eval_with_limits(node: &Node, env: &mut Environment, limits: &Limits) -> EvalResult {
+    eval_with_builtins(node, env, limits, &Builtins::new())
+}
+
+/// Evaluates `node` with `builtins` resolvable by name. Bindings in `env`
+/// shadow builtins of the same name.
+pub fn eval_with_builtins(
+    node: &Node,
+    env: &mut Environment,
+    limits: &Limits,
+    builtins: &Builtins,
+) -> EvalResult {
+    eval_with_budget(node, env, limits, builtins, &mut Budget::new(limits))
+}
+
+/// Evaluates `node` like `eval_with_builtins`, counting its steps against
+/// `budget`, which may be shared with other evaluations.
+pub fn eval_with_budget(
+    node: &Node,
+    env: &mut Environment,
+    limits: &Limits,
+    builtins: &Builtins,
+    budget: &mut Budget,
+) -> EvalResult {
+    let mut ctx = Context {
+        env,
+        limits,
+        builtins,
+        budget,
+        depth: 0,
+    };
     match node {
-        Node::Program(prog) => eval_program(&prog),
-        Node::Statement(stmt) => eval_statement(&stmt),
-        Node::Expression(exp) => eval_expression(&exp),
+        Node::Program(prog) => eval_program(prog, &mut ctx),
+        Node::Statement(stmt) => eval_statement(stmt, &mut ctx),
+        Node::Expression(exp) => eval_expression(exp, &mut ctx),
     }
 }
 
-fn eval_program(prog: &Program) -> EvalResult {
+fn eval_program(prog: &Program, ctx: &mut Context) -> EvalResult {
     let mut result = Object::Int(0);
     for stmt in &prog.statements {
-        let res = eval_statement(&stmt)?;
+        let res = eval_statement(stmt, ctx)?;
         result = res
     }
     Ok(result)
 }
 
-fn eval_statement(stmt: &Statement) -> EvalResult {
+fn eval_statement(stmt: &Statement, ctx: &mut Context) -> EvalResult {
     match stmt {
-        Statement::Expression(exp) => eval_expression(&exp.expression),
-        Statement::Let(_) => Err(EvalError {
-            message: "eval let statement".to_string(),
-        }),
-    }
-}
-
-fn eval_expression(exp: &Expression) -> EvalResult {
-    match exp {
-        Expression::Integer(i) => Ok(Object::Int(*i)),
-        Expression::Prefix(expr) => eval_prefix_expression(expr),
-        Expression::Infix(expr) => eval_infix_expression(expr),
-        _ => Err(EvalError {
-            message: "eval expression".to_string(),
-        }),
-    }
-}
-
-fn eval_infix_expression(exp: &InfixExpression) -> EvalResult {
-    let left = eval_expression(&exp.left)?;
-    let right = eval_expression(&exp.right)?;
-    match exp.operator {
-        Token::Minus => match (left, right) {
-            (Object::Int(l), Object::Int(r)) => Ok(Object::Int(l - r)),
-            _ => Err(EvalError {
-                message: "eval infix expression".to_string(),
-            }),
-        },
-        Token::Plus => match (left, right) {
-            (Object::Int(l), Object::Int(r)) => Ok(Object::Int(l + r)),
-            _ => Err(EvalError {
-                message: "eval infix expression".to_string(),
-            }),
-        },
-        Token::Asterisk => match (left, right) {
-            (Object::Int(l), Object::Int(r)) => Ok(Object::Int(l * r)),
-            _ => Err(EvalError {
-                message: "eval infix expression".to_string(),
-            }),
-        },
-        Token::Slash => match (left, right) {
-            (Object::Int(l), Object::Int(r)) => Ok(Object::Int(l / r)),
-            _ => Err(EvalError {
-                message: "eval infix expression".to_string(),
-            }),
-        },
-        _ => Err(EvalError {
-            message: "eval infix expression".to_string(),
-        }),
-    }
-}
-
-fn eval_prefix_expression(exp: &PrefixExpression) -> EvalResult {
-    let value = eval_expression(&exp.right);
-    match exp.operator {
-        Token::Minus => match value {
-            Ok(Object::Int(i)) => Ok(Object::Int(-i)),
-            _ => Err(EvalError {
-                message: "eval prefix expression".to_string(),
-            }),
-        },
-        _ => Err(EvalError {
-            message: "eval expression".to_string(),
-        }),
+        Statement::Expression(exp) => eval_expression(&exp.expression, ctx),
+        Statement::Let(stmt) => {
+            let value = eval_expression(&stmt.value, ctx)?;
+            check_memory(ctx.limits, ctx.env.usage_after_set(&stmt.name, &value))?;
+            ctx.env.set(stmt.name.clone(), value.clone());
+            Ok(value)
+        }
+        // The session bound the module before the program started.
+        Statement::Import(stmt) => ctx
+            .env
+            .get(&stmt.alias)
+            .ok_or_else(|| EvalError::new(format!("module not loaded: {}", stmt.path))),
+    }
+}
+
+fn eval_expression(exp: &Expression, ctx: &mut Context) -> EvalResult {
+    if ctx.depth >= ctx.limits.max_depth {
+        return Err(EvalError::stack_overflow(ctx.limits.max_depth).at(exp.span()));
+    }
+    ctx.budget.step().map_err(|e| e.at(exp.span()))?;
+    ctx.depth += 1;
+    let result = match exp {
+        Expression::Identifier(ident) => eval_identifier(&ident.name, ctx),
+        Expression::Integer(int) => Ok(Object::Int(int.value)),
+        Expression::Prefix(expr) => eval_prefix_expression(expr, ctx),
+        Expression::Infix(expr) => eval_infix_expression(expr, ctx),
+        Expression::Call(expr) => eval_call_expression(expr, ctx),
+        Expression::Member(expr) => {
+            eval_expression(&expr.object, ctx).and_then(|obj| eval_member(&obj, &expr.name))
+        }
+    };
+    ctx.depth -= 1;
+    result.map_err(|e| e.at(exp.span()))
+}
+
+fn eval_identifier(name: &str, ctx: &Context) -> EvalResult {
+    if let Some(value) = ctx.env.get(name) {
+        return Ok(value);
+    }
+    match ctx.builtins.get(name) {
+        Some(builtin) => Ok(Object::Builtin(builtin.clone())),
+        None => Err(EvalError::new(format!("identifier not found: {}", name))),
+    }
+}
+
+fn eval_infix_expression(exp: &InfixExpression, ctx: &mut Context) -> EvalResult {
+    let left = eval_expression(&exp.left, ctx)?;
+    let right = eval_expression(&exp.right, ctx)?;
+    eval_infix_operator(&exp.operator, &left, &right)
+}
+
+fn eval_prefix_expression(exp: &PrefixExpression, ctx: &mut Context) -> EvalResult {
+    let right = eval_expression(&exp.right, ctx)?;
+    eval_prefix_operator(&exp.operator, &right)
+}
+
+fn eval_call_expression(exp: &CallExpression, ctx: &mut Context) -> EvalResult {
+    let function = eval_expression(&exp.function, ctx)?;
+    let mut args = Vec::with_capacity(exp.arguments.len());
+    for arg in &exp.arguments {
+        args.push(eval_expression(arg, ctx)?);
+    }
+    apply_function(&function, &args)
+}
+
+/// Calls `function` with `args`, failing if it is not callable.
+pub fn apply_function(function: &Object, args: &[Object]) -> EvalResult {
+    match function {
+        Object::Builtin(builtin) => builtin.call(args),
+        obj => Err(EvalError::new(format!(
+            "not a function: {}",
+            obj.type_name()
+        ))),
+    }
+}
+
+/// Looks up `name` in `object`, a module or another hash with string keys.
+pub fn eval_member(object: &Object, name: &str) -> EvalResult {
+    match object {
+        Object::Hash(pairs) => pairs
+            .get(&HashKey::Str(name.to_string()))
+            .cloned()
+            .ok_or_else(|| EvalError::new(format!("unknown member: {}", name))),
+        obj => Err(EvalError::new(format!(
+            "no member {} in {}",
+            name,
+            obj.type_name()
+        ))),
+    }
+}
+
+/// Applies an infix operator to two objects of any type.
+pub fn eval_infix_operator(operator: &TokenKind, left: &Object, right: &Object) -> EvalResult {
+    match (left, right) {
+        (Object::Int(l), Object::Int(r)) => eval_integer_infix_expression(operator, *l, *r),
+        (l, r) if l.type_name() != r.type_name() => Err(EvalError::new(format!(
+            "type mismatch: {} {} {}",
+            l.type_name(),
+            operator.literal(),
+            r.type_name()
+        ))),
+        (l, r) => Err(EvalError::new(format!(
+            "unknown operator: {} {} {}",
+            l.type_name(),
+            operator.literal(),
+            r.type_name()
+        ))),
+    }
+}
+
+/// Applies a prefix operator to an object of any type.
+pub fn eval_prefix_operator(operator: &TokenKind, right: &Object) -> EvalResult {
+    match right {
+        Object::Int(i) => eval_integer_prefix_expression(operator, *i),
+        obj => Err(EvalError::new(format!(
+            "unknown operator: {}{}",
+            operator.literal(),
+            obj.type_name()
+        ))),
+    }
+}
+
+/// Applies an infix operator to two integers. Division by zero and overflow
+/// are errors rather than panics.
+pub fn eval_integer_infix_expression(operator: &TokenKind, left: i64, right: i64) -> EvalResult {
+    let value = match operator {
+        TokenKind::Minus => left.checked_sub(right),
+        TokenKind::Plus => left.checked_add(right),
+        TokenKind::Asterisk => left.checked_mul(right),
+        TokenKind::Slash if right == 0 => {
+            return Err(EvalError::new("division by zero".to_string()))
+        }
+        TokenKind::Slash => left.checked_div(right),
+        _ => return Err(EvalError::new("eval infix expression".to_string())),
+    };
+    value
+        .map(Object::Int)
+        .ok_or_else(|| EvalError::new("integer overflow".to_string()))
+}
+
+/// Applies a prefix operator to an integer.
+pub fn eval_integer_prefix_expression(operator: &TokenKind, value: i64) -> EvalResult {
+    match operator {
+        TokenKind::Minus => value
+            .checked_neg()
+            .map(Object::Int)
+            .ok_or_else(|| EvalError::new("integer overflow".to_string())),
+        _ => Err(EvalError::new("eval prefix expression".to_string())),
     }
 }
 
 #[cfg(test)]
 mod test {
-    use parser;
     use super::*;
+    use parser;
+    use token::Span;
 
     #[test]
     fn eval_integer_expression() {
@@ -113,14 +404,194 @@ mod test {
 
         for t in test {
             let obj = match parser::parse(t.0) {
-                Ok(node) => eval(&node).expect(t.0),
-                Err(e) => panic!(e),
+                Ok(node) => eval(&node, &mut Environment::new()).expect(t.0),
+                Err(e) => panic!("{}", e),
+            };
+
+            match obj {
+                Object::Int(i) => assert_eq!(t.1, i),
+                obj => panic!("expected integer but got {:?}", obj),
+            }
+        }
+    }
+
+    #[test]
+    fn eval_let_statement() {
+        let test = vec![
+            ("let birthday = 1103; birthday;", 1103),
+            ("let a = 1103; let b = a * 2; b - a;", 1103),
+        ];
+
+        for t in test {
+            let obj = match parser::parse(t.0) {
+                Ok(node) => eval(&node, &mut Environment::new()).expect(t.0),
+                Err(e) => panic!("{}", e),
             };
 
             match obj {
                 Object::Int(i) => assert_eq!(t.1, i),
-                _ => panic!(),
+                obj => panic!("expected integer but got {:?}", obj),
+            }
+        }
+    }
+
+    #[test]
+    fn eval_arithmetic_errors() {
+        let test = vec![
+            ("1103 / 0;", "division by zero"),
+            ("1103 / (1 - 1);", "division by zero"),
+            ("9223372036854775807 + 1;", "integer overflow"),
+            ("(-9223372036854775807 - 1) / -1;", "integer overflow"),
+            ("-(-9223372036854775807 - 1);", "integer overflow"),
+            ("-birthday;", "identifier not found: birthday"),
+        ];
+
+        for t in test {
+            let node = parser::parse(t.0).expect(t.0);
+            let err = eval(&node, &mut Environment::new()).unwrap_err();
+            assert_eq!(t.1, err.message, "{}", t.0);
+        }
+    }
+
+    #[test]
+    fn eval_depth_limit() {
+        let mut exp = Expression::Integer(IntegerLiteral {
+            value: 1103,
+            span: Span::default(),
+        });
+        for _ in 0..DEFAULT_MAX_DEPTH - 1 {
+            exp = Expression::Prefix(Box::new(PrefixExpression {
+                operator: TokenKind::Minus,
+                right: exp,
+                span: Span::default(),
+            }));
+        }
+        let mut node = Node::Expression(Box::new(exp));
+        let obj = eval(&node, &mut Environment::new()).unwrap();
+        assert_eq!("-1103", obj.inspect());
+
+        if let Node::Expression(exp) = node {
+            node = Node::Expression(Box::new(Expression::Prefix(Box::new(PrefixExpression {
+                operator: TokenKind::Minus,
+                right: *exp,
+                span: Span::default(),
+            }))));
+        }
+        let err = eval(&node, &mut Environment::new()).unwrap_err();
+        assert_eq!(ErrorKind::StackOverflow, err.kind);
+        assert_eq!(
+            "stack overflow: evaluation nested deeper than 1000",
+            err.message
+        );
+
+        let limits = Limits {
+            max_depth: 2,
+            ..Limits::default()
+        };
+        let node = parser::parse("1 + 2; 1 + 2 * 3;").unwrap();
+        let err = eval_with_limits(&node, &mut Environment::new(), &limits).unwrap_err();
+        assert_eq!(
+            "stack overflow: evaluation nested deeper than 2",
+            err.message
+        );
+    }
+
+    #[test]
+    fn eval_fuel_and_timeout() {
+        let node = parser::parse("let a = 1 + 2; a * 3;").unwrap();
+        let mut env = Environment::new();
+        let limits = Limits {
+            fuel: Some(4),
+            ..Limits::default()
+        };
+        let err = eval_with_limits(&node, &mut env, &limits).unwrap_err();
+        assert_eq!(ErrorKind::OutOfFuel, err.kind);
+        assert_eq!(
+            "out of fuel: evaluation took more than 4 steps",
+            err.message
+        );
+
+        // The fuel is per evaluation and the environment stays usable.
+        let limits = Limits {
+            fuel: Some(6),
+            ..Limits::default()
+        };
+        let obj = eval_with_limits(&node, &mut env, &limits).unwrap();
+        assert_eq!("9", obj.inspect());
+
+        let limits = Limits {
+            timeout: Some(Duration::from_secs(0)),
+            ..Limits::default()
+        };
+        let err = eval_with_limits(&node, &mut env, &limits).unwrap_err();
+        assert_eq!(ErrorKind::Timeout, err.kind);
+        assert_eq!("timeout: evaluation took longer than 0ns", err.message);
+    }
+
+    #[test]
+    fn eval_memory_limit() {
+        let node = parser::parse("let a = 1; let b = 2;").unwrap();
+        let mut env = Environment::new();
+        let binding = Object::Int(0).size();
+        let limits = Limits {
+            max_memory: Some(binding),
+            ..Limits::default()
+        };
+        let err = eval_with_limits(&node, &mut env, &limits).unwrap_err();
+        assert_eq!(ErrorKind::OutOfMemory, err.kind);
+        assert_eq!(
+            format!(
+                "out of memory: bindings would use {} bytes, over the limit of {}",
+                2 * binding,
+                binding
+            ),
+            err.message
+        );
+        assert_eq!(binding, env.memory_usage());
+        assert!(env.get("b").is_none());
+    }
+
+    #[test]
+    fn eval_builtin_calls() {
+        let mut builtins = Builtins::new();
+        builtins.register("add", 2, "Adds two integers.", |args| {
+            eval_infix_operator(&TokenKind::Plus, &args[0], &args[1])
+        });
+        let tests = vec![
+            ("add(1, 2);", Ok("3")),
+            ("add(add(1, 2), 3) * 2;", Ok("12")),
+            ("add;", Ok("builtin add")),
+            ("let add = 1; add;", Ok("1")),
+            (
+                "add(1);",
+                Err("wrong number of arguments to add: got 1, want 2"),
+            ),
+            ("1(2);", Err("not a function: INTEGER")),
+            ("add + 1;", Err("type mismatch: BUILTIN + INTEGER")),
+            ("add * add;", Err("unknown operator: BUILTIN * BUILTIN")),
+            ("-add;", Err("unknown operator: -BUILTIN")),
+        ];
+
+        for t in tests {
+            let node = parser::parse(t.0).expect(t.0);
+            let result = eval_with_builtins(
+                &node,
+                &mut Environment::new(),
+                &Limits::default(),
+                &builtins,
+            );
+            match (t.1, result) {
+                (Ok(expected), Ok(obj)) => assert_eq!(expected, obj.inspect(), "{}", t.0),
+                (Err(expected), Err(err)) => assert_eq!(expected, err.message, "{}", t.0),
+                (_, result) => panic!("{}: unexpected {:?}", t.0, result),
             }
         }
     }
+
+    #[test]
+    fn eval_unknown_identifier() {
+        let node = parser::parse("birthday;").unwrap();
+        let err = eval(&node, &mut Environment::new()).unwrap_err();
+        assert_eq!("identifier not found: birthday", err.message);
+    }
 }
@@ -0,0 +1,309 @@
+//! The C API declared in `include/monkey.h`. Interpreters, results, values
+//! and strings handed out here are owned by the caller and released with the
+//! matching `_free` function.
+
+use evaluator::{ErrorKind, EvalError};
+use interpreter::Interpreter;
+use object::Object;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_void};
+use std::ptr;
+
+/// The outcome of `monkey_eval`: a value or an error message and kind.
+pub struct MonkeyResult {
+    value: Option<Object>,
+    error: Option<CString>,
+    error_kind: MonkeyErrorKind,
+}
+
+/// Why an evaluation failed, as `monkey_result_error_kind` reports it.
+#[repr(C)]
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum MonkeyErrorKind {
+    None,
+    Syntax,
+    Runtime,
+    StackOverflow,
+    OutOfFuel,
+    Timeout,
+    OutOfMemory,
+    PermissionDenied,
+}
+
+impl From<ErrorKind> for MonkeyErrorKind {
+    fn from(kind: ErrorKind) -> MonkeyErrorKind {
+        match kind {
+            ErrorKind::Syntax => MonkeyErrorKind::Syntax,
+            ErrorKind::Runtime => MonkeyErrorKind::Runtime,
+            ErrorKind::StackOverflow => MonkeyErrorKind::StackOverflow,
+            ErrorKind::OutOfFuel => MonkeyErrorKind::OutOfFuel,
+            ErrorKind::Timeout => MonkeyErrorKind::Timeout,
+            ErrorKind::OutOfMemory => MonkeyErrorKind::OutOfMemory,
+            ErrorKind::PermissionDenied => MonkeyErrorKind::PermissionDenied,
+        }
+    }
+}
+
+/// The type of a value, as `monkey_value_type` reports it.
+#[repr(C)]
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum MonkeyType {
+    Null,
+    Integer,
+    Boolean,
+    String,
+    Array,
+    Hash,
+    Builtin,
+}
+
+/// A builtin implemented in C. It returns a new value, or NULL after
+/// pointing `error` at a message that stays valid until it returns.
+pub type MonkeyCallback = unsafe extern "C" fn(
+    user_data: *mut c_void,
+    args: *const *const Object,
+    nargs: usize,
+    error: *mut *const c_char,
+) -> *mut Object;
+
+fn to_cstring(s: &str) -> CString {
+    // Interior NULs would truncate the string on the C side.
+    CString::new(s.replace('\0', "\\0")).unwrap()
+}
+
+#[no_mangle]
+pub extern "C" fn monkey_interpreter_new() -> *mut Interpreter {
+    Box::into_raw(Box::new(Interpreter::new()))
+}
+
+/// # Safety
+///
+/// `interp` must come from `monkey_interpreter_new` and not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_interpreter_free(interp: *mut Interpreter) {
+    if !interp.is_null() {
+        drop(Box::from_raw(interp));
+    }
+}
+
+/// Evaluates `source` and returns its result, which is never NULL.
+///
+/// # Safety
+///
+/// `interp` must be a live interpreter and `source` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_eval(
+    interp: *mut Interpreter,
+    source: *const c_char,
+) -> *mut MonkeyResult {
+    let interp = &mut *interp;
+    let result = match CStr::from_ptr(source).to_str() {
+        Ok(source) => interp.eval_str(source).map_err(|d| (d.kind, d.message)),
+        Err(_) => Err((ErrorKind::Syntax, "source is not valid UTF-8".to_string())),
+    };
+    let result = match result {
+        Ok(value) => MonkeyResult {
+            value: Some(value),
+            error: None,
+            error_kind: MonkeyErrorKind::None,
+        },
+        Err((kind, message)) => MonkeyResult {
+            value: None,
+            error: Some(to_cstring(&message)),
+            error_kind: kind.into(),
+        },
+    };
+    Box::into_raw(Box::new(result))
+}
+
+/// Makes `callback` callable from scripts as `name` with exactly `arity`
+/// arguments. Returns false if `name` is not valid UTF-8.
+///
+/// # Safety
+///
+/// `interp` must be a live interpreter and `name` a NUL-terminated string.
+/// `callback` is called with `user_data` for as long as the interpreter
+/// lives.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_register_builtin(
+    interp: *mut Interpreter,
+    name: *const c_char,
+    arity: usize,
+    callback: MonkeyCallback,
+    user_data: *mut c_void,
+) -> bool {
+    let name = match CStr::from_ptr(name).to_str() {
+        Ok(name) => name.to_string(),
+        Err(_) => return false,
+    };
+    let builtin_name = name.clone();
+    (*interp).register_builtin(&name, arity, "", move |args| {
+        let args: Vec<*const Object> = args.iter().map(|a| a as *const Object).collect();
+        let mut error = ptr::null();
+        let value = callback(user_data, args.as_ptr(), args.len(), &mut error);
+        if !value.is_null() {
+            return Ok(*Box::from_raw(value));
+        }
+        let message = if error.is_null() {
+            format!("{} failed", builtin_name)
+        } else {
+            CStr::from_ptr(error).to_string_lossy().into_owned()
+        };
+        Err(EvalError::new(message))
+    });
+    true
+}
+
+/// # Safety
+///
+/// `result` must come from `monkey_eval` and not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_result_free(result: *mut MonkeyResult) {
+    if !result.is_null() {
+        drop(Box::from_raw(result));
+    }
+}
+
+/// # Safety
+///
+/// `result` must be a live result.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_result_is_ok(result: *const MonkeyResult) -> bool {
+    (*result).value.is_some()
+}
+
+/// Returns the value of a successful evaluation, or NULL. The value belongs
+/// to the result.
+///
+/// # Safety
+///
+/// `result` must be a live result.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_result_value(result: *const MonkeyResult) -> *const Object {
+    match (*result).value {
+        Some(ref value) => value,
+        None => ptr::null(),
+    }
+}
+
+/// Returns the error message of a failed evaluation, or NULL. The string
+/// belongs to the result.
+///
+/// # Safety
+///
+/// `result` must be a live result.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_result_error(result: *const MonkeyResult) -> *const c_char {
+    match (*result).error {
+        Some(ref error) => error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Returns why an evaluation failed, or `MonkeyErrorKind::None` if it
+/// succeeded.
+///
+/// # Safety
+///
+/// `result` must be a live result.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_result_error_kind(result: *const MonkeyResult) -> MonkeyErrorKind {
+    (*result).error_kind
+}
+
+#[no_mangle]
+pub extern "C" fn monkey_value_int(value: i64) -> *mut Object {
+    Box::into_raw(Box::new(Object::Int(value)))
+}
+
+/// Returns a new string value, or NULL if `value` is not valid UTF-8.
+///
+/// # Safety
+///
+/// `value` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_value_string(value: *const c_char) -> *mut Object {
+    match CStr::from_ptr(value).to_str() {
+        Ok(s) => Box::into_raw(Box::new(Object::Str(s.to_string()))),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// `value` must come from a `monkey_value_` constructor and not be used
+/// again.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_value_free(value: *mut Object) {
+    if !value.is_null() {
+        drop(Box::from_raw(value));
+    }
+}
+
+/// # Safety
+///
+/// `value` must be a live value.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_value_type(value: *const Object) -> MonkeyType {
+    match *value {
+        Object::Null => MonkeyType::Null,
+        Object::Int(_) => MonkeyType::Integer,
+        Object::Boolean(_) => MonkeyType::Boolean,
+        Object::Str(_) => MonkeyType::String,
+        Object::Array(_) => MonkeyType::Array,
+        Object::Hash(_) => MonkeyType::Hash,
+        Object::Builtin(_) => MonkeyType::Builtin,
+    }
+}
+
+/// Stores an integer value in `out` and returns true, or returns false for
+/// any other type.
+///
+/// # Safety
+///
+/// `value` must be a live value and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_value_as_int(value: *const Object, out: *mut i64) -> bool {
+    match *value {
+        Object::Int(i) => {
+            *out = i;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Returns a copy of a string value, or NULL for any other type. Free it
+/// with `monkey_string_free`.
+///
+/// # Safety
+///
+/// `value` must be a live value.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_value_as_string(value: *const Object) -> *mut c_char {
+    match *value {
+        Object::Str(ref s) => to_cstring(s).into_raw(),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Returns the value printed as the REPL would. Free it with
+/// `monkey_string_free`.
+///
+/// # Safety
+///
+/// `value` must be a live value.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_value_inspect(value: *const Object) -> *mut c_char {
+    to_cstring(&(*value).inspect()).into_raw()
+}
+
+/// # Safety
+///
+/// `s` must come from this library and not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
@@ -0,0 +1,259 @@
+use color;
+use lexer::Lexer;
+use repl;
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::{CmdKind, Highlighter};
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use session::Session;
+use std::borrow::Cow;
+use std::io;
+use std::path::PathBuf;
+use token::TokenKind;
+
+const HISTORY_FILE: &str = ".monkey_history";
+const KEYWORDS: &[&str] = &["as", "import", "let"];
+
+struct ReplHelper {
+    // Names bound in the session environment and its builtins, refreshed
+    // after every input.
+    names: Vec<String>,
+    color: bool,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, words) = complete_word(line, pos, &self.names);
+        let candidates = words
+            .into_iter()
+            .map(|w| Pair {
+                display: w.clone(),
+                replacement: w,
+            })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        if self.color {
+            Cow::Owned(highlight(line))
+        } else {
+            Cow::Borrowed(line)
+        }
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _kind: CmdKind) -> bool {
+        self.color
+    }
+}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+/// Runs the REPL on a terminal with line editing, history search (Ctrl-R)
+/// and tab completion. History is kept in `~/.monkey_history`. Input is
+/// syntax highlighted unless colors are disabled.
+pub fn start(mut session: Session) -> rustyline::Result<()> {
+    let color = color::enabled_for(&io::stdout());
+    let mut rl = Editor::<ReplHelper, DefaultHistory>::new()?;
+    rl.set_helper(Some(ReplHelper {
+        names: completion_names(&session),
+        color,
+    }));
+
+    let history = history_path();
+    if let Some(ref path) = history {
+        // The file does not exist on the first run.
+        let _ = rl.load_history(path);
+    }
+
+    let mut input = String::new();
+    loop {
+        let prompt = if input.is_empty() {
+            repl::PROMPT
+        } else {
+            repl::CONTINUATION_PROMPT
+        };
+        let line = match rl.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                input.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e),
+        };
+
+        if line.trim().is_empty() {
+            input.clear();
+            continue;
+        }
+
+        if input.is_empty() && repl::is_command(&line) {
+            rl.add_history_entry(line.as_str())?;
+            println!("{}", repl::run_command(line.trim(), &mut session, color));
+        } else {
+            input.push_str(&line);
+            input.push('\n');
+            if repl::is_incomplete(&input) {
+                continue;
+            }
+
+            rl.add_history_entry(input.trim_end())?;
+            println!("{}", repl::eval_input(&input, &mut session, color));
+            input.clear();
+        }
+
+        if let Some(helper) = rl.helper_mut() {
+            helper.names = completion_names(&session);
+        }
+    }
+
+    if let Some(ref path) = history {
+        rl.save_history(path)?;
+    }
+    Ok(())
+}
+
+/// Returns the bound names and builtin names that tab completion offers.
+fn completion_names(session: &Session) -> Vec<String> {
+    let mut names = session.names();
+    names.extend(session.builtins().iter().map(|b| b.name.clone()));
+    names
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::home_dir().map(|home| home.join(HISTORY_FILE))
+}
+
+/// Colors keywords, literals and operators in `line`.
+fn highlight(line: &str) -> String {
+    let mut out = String::new();
+    let mut l = Lexer::new(line);
+    let mut last = 0;
+    loop {
+        let tok = l.next_token();
+        if tok == TokenKind::Eof {
+            break;
+        }
+        let span = l.span();
+        out.push_str(&line[last..span.start]);
+
+        let text = &line[span.start..span.end];
+        let style = match tok {
+            TokenKind::Let | TokenKind::Function | TokenKind::Import | TokenKind::As => {
+                Some(color::KEYWORD)
+            }
+            TokenKind::Int(_) | TokenKind::Str(_) => Some(color::LITERAL),
+            TokenKind::Assign
+            | TokenKind::Plus
+            | TokenKind::Minus
+            | TokenKind::Asterisk
+            | TokenKind::Slash => Some(color::OPERATOR),
+            TokenKind::Illegal => Some(color::ERROR),
+            _ => None,
+        };
+        match style {
+            Some(style) => out.push_str(&color::paint(text, style, true)),
+            None => out.push_str(text),
+        }
+        last = span.end;
+    }
+    out.push_str(&line[last..]);
+    out
+}
+
+/// Finds the identifier ending at `pos` and returns its start offset together
+/// with the keywords and bound names it is a prefix of.
+fn complete_word(line: &str, pos: usize, names: &[String]) -> (usize, Vec<String>) {
+    let start = line[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, ch)| ch.is_alphabetic() || ch == '_')
+        .last()
+        .map_or(pos, |(i, _)| i);
+    let prefix = &line[start..pos];
+    if prefix.is_empty() {
+        return (pos, Vec::new());
+    }
+
+    let mut words: Vec<String> = KEYWORDS
+        .iter()
+        .map(|k| k.to_string())
+        .chain(names.iter().cloned())
+        .filter(|w| w.starts_with(prefix))
+        .collect();
+    words.sort();
+    words.dedup();
+    (start, words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlight_tokens() {
+        assert_eq!(
+            "\x1b[35mlet\x1b[0m a \x1b[36m=\x1b[0m \x1b[33m1\x1b[0m;  ",
+            highlight("let a = 1;  ")
+        );
+    }
+
+    #[test]
+    fn complete_builtins() {
+        let mut session = Session::default();
+        session.register_fn("double", "", |a: i64| a * 2);
+        session.eval("let dozen = 12;").unwrap();
+        let names = completion_names(&session);
+
+        assert_eq!(
+            (0, vec!["puts".to_string()]),
+            complete_word("pu", 2, &names)
+        );
+        assert_eq!(
+            (0, vec!["double".to_string(), "dozen".to_string()]),
+            complete_word("do", 2, &names)
+        );
+        assert_eq!(
+            (0, vec!["read_file".to_string(), "read_line".to_string()]),
+            complete_word("read_", 5, &names)
+        );
+    }
+
+    #[test]
+    fn complete_keywords_and_names() {
+        let names = vec!["birthday".to_string(), "lenient".to_string()];
+
+        assert_eq!(
+            (0, vec!["let".to_string()]),
+            complete_word("let", 3, &names)
+        );
+        assert_eq!(
+            (4, vec!["birthday".to_string()]),
+            complete_word("1 + bi", 6, &names)
+        );
+        assert_eq!(
+            (4, vec!["lenient".to_string(), "let".to_string()]),
+            complete_word("1 + l;", 5, &names)
+        );
+        assert_eq!((5, Vec::<String>::new()), complete_word("1 + (", 5, &names));
+    }
+}
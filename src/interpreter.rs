@@ -0,0 +1,351 @@
+use ast::Program;
+use builtins::Builtins;
+use capabilities::Capabilities;
+use convert::NativeFunction;
+use diagnostic::Diagnostic;
+use evaluator::{self, EvalResult, Limits};
+use object::Object;
+use session::{Engine, Session};
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// The entry point for Rust hosts: load a script once, then read and write
+/// its globals between calls.
+#[derive(Debug, Default)]
+pub struct Interpreter {
+    session: Session,
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter::default()
+    }
+
+    pub fn new_with_engine(engine: Engine) -> Interpreter {
+        Interpreter {
+            session: Session::new(engine),
+        }
+    }
+
+    pub fn engine(&self) -> Engine {
+        self.session.engine()
+    }
+
+    /// Sets the limits every later evaluation runs under.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.session.set_limits(limits);
+    }
+
+    /// Sets how deeply expressions may nest in parsed source.
+    pub fn set_max_parse_depth(&mut self, max_depth: usize) {
+        self.session.set_max_parse_depth(max_depth);
+    }
+
+    /// Grants scripts the I/O in `capabilities`. Without it, `puts`,
+    /// `read_line`, `read_file` and `write_file` fail with a permission error.
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.session.set_capabilities(capabilities);
+    }
+
+    /// Sends the output of `puts` to `output`.
+    pub fn set_output(&mut self, output: Box<dyn io::Write>) {
+        self.session.set_output(output);
+    }
+
+    /// Runs programs through `optimizer::optimize` before executing them.
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.session.set_optimize(optimize);
+    }
+
+    /// Evaluates `source` against the interpreter's globals and returns the
+    /// value of its last statement.
+    pub fn eval_str(&mut self, source: &str) -> Result<Object, Diagnostic> {
+        self.session.eval(source)
+    }
+
+    /// Evaluates an already parsed program like `eval_str`.
+    pub fn eval_program(&mut self, program: Program) -> Result<Object, Diagnostic> {
+        self.session.eval_program(program)
+    }
+
+    /// Reads a script from `path` and evaluates it like `eval_str`. Its
+    /// imports resolve against its directory, but only load files from the
+    /// directories granted with `allow_module_dir`.
+    pub fn eval_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Object, Diagnostic> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)
+            .map_err(|e| Diagnostic::error(format!("{}: {}", path.display(), e)))?;
+        self.session.set_script_path(path);
+        self.eval_str(&source)
+    }
+
+    /// Adds a module that scripts load with `import "name" as alias;`.
+    pub fn add_module(&mut self, name: &str, source: &str) {
+        self.session.add_module(name, source);
+    }
+
+    /// Lets imports load files anywhere below `dir`.
+    pub fn allow_module_dir<P: Into<PathBuf>>(&mut self, dir: P) {
+        self.session.allow_module_dir(dir);
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Object> {
+        self.session.get(name)
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Object) {
+        self.session.set(name, value);
+    }
+
+    /// Returns the names of all globals in sorted order.
+    pub fn globals(&self) -> Vec<String> {
+        self.session.names()
+    }
+
+    /// Approximate bytes held by the globals, as counted against
+    /// `Limits::max_memory`.
+    pub fn memory_usage(&self) -> usize {
+        self.session.memory_usage()
+    }
+
+    /// Registers a Rust function that scripts can call as `name` with
+    /// exactly `arity` arguments. See `Session::register_builtin`.
+    pub fn register_builtin<F>(&mut self, name: &str, arity: usize, doc: &str, function: F)
+    where
+        F: Fn(&[Object]) -> EvalResult + 'static,
+    {
+        self.session.register_builtin(name, arity, doc, function);
+    }
+
+    /// Registers a Rust function with typed parameters and result. Its
+    /// arity follows from the signature, and arguments of the wrong type
+    /// fail with an error naming their position.
+    pub fn register_fn<F, Args>(&mut self, name: &str, doc: &str, function: F)
+    where
+        F: NativeFunction<Args>,
+    {
+        self.session.register_fn(name, doc, function);
+    }
+
+    pub fn builtins(&self) -> &Builtins {
+        self.session.builtins()
+    }
+
+    /// Calls the global function `name` with `args`.
+    pub fn call_function(&mut self, name: &str, args: &[Object]) -> Result<Object, Diagnostic> {
+        match self.get_global(name) {
+            Some(function) => evaluator::apply_function(&function, args).map_err(Diagnostic::from),
+            None => Err(Diagnostic::error(format!("identifier not found: {}", name))),
+        }
+    }
+}
+
+/// An output sink that keeps what is written to it, for hosts that show
+/// script output themselves. Clones share the same buffer.
+#[derive(Debug, Clone, Default)]
+pub struct CapturedOutput {
+    buffer: Rc<RefCell<Vec<u8>>>,
+}
+
+impl CapturedOutput {
+    pub fn new() -> CapturedOutput {
+        CapturedOutput::default()
+    }
+
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.buffer.borrow()).into_owned()
+    }
+}
+
+impl io::Write for CapturedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evaluator::ErrorKind;
+    use std::env;
+    use token::TokenKind;
+
+    #[test]
+    fn globals_persist() {
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut interp = Interpreter::new_with_engine(engine);
+            interp.eval_str("let a = 1103;").unwrap();
+            interp.set_global("b", Object::Int(2));
+            assert_eq!("2206", interp.eval_str("a * b;").unwrap().inspect());
+
+            interp.set_global("a", Object::Int(1));
+            assert_eq!("1", interp.get_global("a").unwrap().inspect());
+            assert!(interp.get_global("c").is_none());
+            assert_eq!(vec!["a", "b"], interp.globals());
+        }
+    }
+
+    #[test]
+    fn limits() {
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut interp = Interpreter::new_with_engine(engine);
+            assert_eq!(0, interp.memory_usage());
+            interp.eval_str("let a = 1;").unwrap();
+            assert!(interp.memory_usage() > 0, "{}", engine);
+
+            interp.set_max_parse_depth(2);
+            let err = interp.eval_str("((1));").unwrap_err();
+            assert_eq!(ErrorKind::StackOverflow, err.kind, "{}", engine);
+        }
+    }
+
+    #[test]
+    fn captured_output() {
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut interp = Interpreter::new_with_engine(engine);
+            let output = CapturedOutput::new();
+            interp.set_output(Box::new(output.clone()));
+            let result = interp.eval_str("let a = 1103; puts(a); puts(a * 2);");
+            assert_eq!("null", result.unwrap().inspect());
+            assert_eq!("1103\n2206\n", output.contents());
+        }
+    }
+
+    #[test]
+    fn file_capabilities() {
+        let dir = env::temp_dir().join(format!("monkey-sandbox-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let inside = dir.join("note.txt").display().to_string();
+        let outside = dir.join("../monkey-escape.txt").display().to_string();
+
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut interp = Interpreter::new_with_engine(engine);
+            interp.set_global("inside", Object::Str(inside.clone()));
+            interp.set_global("outside", Object::Str(outside.clone()));
+            let err = interp.eval_str("read_file(inside);").unwrap_err();
+            assert_eq!(
+                "permission denied: no filesystem access was granted",
+                err.message
+            );
+            assert_eq!(ErrorKind::PermissionDenied, err.kind);
+
+            let mut capabilities = Capabilities::new();
+            capabilities.allow_fs_root(&dir);
+            interp.set_capabilities(capabilities);
+            interp.eval_str("write_file(inside, inside);").unwrap();
+            let result = interp.eval_str("read_file(inside);").unwrap();
+            assert_eq!(inside, result.inspect());
+            let err = interp.eval_str("write_file(outside, inside);").unwrap_err();
+            assert!(err.message.ends_with("is outside the allowed directories"));
+            assert_eq!(ErrorKind::PermissionDenied, err.kind);
+            assert!(interp.eval_str("puts(1);").is_err());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn import_files() {
+        let dir = env::temp_dir().join(format!("monkey-modules-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(
+            dir.join("main.mk"),
+            "import \"lib/math.mk\" as math; math.twice(21);",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("lib/math.mk"),
+            "import \"./base.mk\" as base; let twice = base.double;",
+        )
+        .unwrap();
+        fs::write(dir.join("lib/base.mk"), "let double = times_two;").unwrap();
+
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut interp = Interpreter::new_with_engine(engine);
+            interp.register_fn("times_two", "", |a: i64| a * 2);
+            // Knowing where the script lives grants no access to its files.
+            let err = interp.eval_file(dir.join("main.mk")).unwrap_err();
+            assert_eq!(ErrorKind::PermissionDenied, err.kind, "{}", err.message);
+
+            interp.allow_module_dir(&dir);
+            assert_eq!(
+                "42",
+                interp.eval_file(dir.join("main.mk")).unwrap().inspect()
+            );
+            let err = interp
+                .eval_str("import \"../escape.mk\" as escape;")
+                .unwrap_err();
+            assert!(
+                err.message.contains("is outside the allowed directories"),
+                "{}",
+                err.message
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn eval_file() {
+        let path = env::temp_dir().join(format!("monkey-interpreter-{}.mk", std::process::id()));
+        fs::write(&path, "let birthday = 1103;\nbirthday * 2;\n").unwrap();
+        let mut interp = Interpreter::new();
+        let result = interp.eval_file(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!("2206", result.unwrap().inspect());
+        assert_eq!("1103", interp.get_global("birthday").unwrap().inspect());
+
+        let err = interp.eval_file(&path).unwrap_err();
+        assert!(err.message.starts_with(&path.display().to_string()));
+    }
+
+    #[test]
+    fn typed_functions() {
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut interp = Interpreter::new_with_engine(engine);
+            interp.register_fn("greet", "", |name: String, times: i64| {
+                vec![format!("hello {}", name); times as usize]
+            });
+            interp.set_global("name", Object::Str("monkey".to_string()));
+
+            let result = interp.eval_str("greet(name, 2);").unwrap();
+            assert_eq!("[hello monkey, hello monkey]", result.inspect());
+            let err = interp.eval_str("greet(1, name);").unwrap_err();
+            assert_eq!(
+                "argument 1 to greet: expected STRING, got INTEGER",
+                err.message
+            );
+        }
+    }
+
+    #[test]
+    fn call_function() {
+        let mut interp = Interpreter::new();
+        interp.register_builtin("negate", 1, "Negates an integer.", |args| {
+            evaluator::eval_prefix_operator(&TokenKind::Minus, &args[0])
+        });
+        let result = interp.call_function("negate", &[Object::Int(1103)]);
+        assert_eq!("-1103", result.unwrap().inspect());
+        assert_eq!(
+            "Negates an integer.",
+            interp.builtins().get("negate").unwrap().doc
+        );
+
+        let err = interp.call_function("negate", &[]).unwrap_err();
+        assert_eq!(
+            "wrong number of arguments to negate: got 0, want 1",
+            err.message
+        );
+        interp.set_global("a", Object::Int(1));
+        let err = interp.call_function("a", &[]).unwrap_err();
+        assert_eq!("not a function: INTEGER", err.message);
+        let err = interp.call_function("f", &[]).unwrap_err();
+        assert_eq!("identifier not found: f", err.message);
+    }
+}
@@ -1,46 +1,68 @@
 use std::iter::Peekable;
 use std::str::Chars;
 use token;
-use token::Token;
+use token::{Span, SpannedToken, TokenKind};
 
 #[derive(Debug)]
 pub struct Lexer<'a> {
     input: Peekable<Chars<'a>>,
+    position: usize,
+    start: usize,
 }
 
 impl<'a> Lexer<'a> {
-    pub fn new(input: &'a str) -> Lexer {
+    pub fn new(input: &'a str) -> Lexer<'a> {
         Lexer {
             input: input.chars().peekable(),
+            position: 0,
+            start: 0,
         }
     }
 
-    pub fn next_token(&mut self) -> Token {
+    /// Returns the span of the token last returned by `next_token`.
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.start,
+            end: self.position,
+        }
+    }
+
+    pub fn next_token(&mut self) -> TokenKind {
         self.skip_whitespace();
+        self.start = self.position;
         match self.read_char() {
-            Some('=') => Token::Assign,
-            Some('+') => Token::Plus,
-            Some('-') => Token::Minus,
-            Some('*') => Token::Asterisk,
-            Some('/') => Token::Slash,
-            Some('(') => Token::Lparen,
-            Some(')') => Token::Rparen,
-            Some('{') => Token::Lbrace,
-            Some('}') => Token::Rbrace,
-            Some(',') => Token::Comma,
-            Some(';') => Token::Semicolon,
+            Some('=') => TokenKind::Assign,
+            Some('+') => TokenKind::Plus,
+            Some('-') => TokenKind::Minus,
+            Some('*') => TokenKind::Asterisk,
+            Some('/') => TokenKind::Slash,
+            Some('(') => TokenKind::Lparen,
+            Some(')') => TokenKind::Rparen,
+            Some('{') => TokenKind::Lbrace,
+            Some('}') => TokenKind::Rbrace,
+            Some(',') => TokenKind::Comma,
+            Some('.') => TokenKind::Dot,
+            Some(';') => TokenKind::Semicolon,
+            // An unterminated string is not a valid token.
+            Some('"') => match self.read_string() {
+                Some(value) => TokenKind::Str(value),
+                None => TokenKind::Illegal,
+            },
             Some(ch) => {
                 if is_letter(ch) {
                     let ident = self.read_identifier(ch);
-                    let tok = token::lookup_ident(ident);
-                    tok
-                } else if ch.is_digit(10) {
-                    Token::Int(self.read_number(ch))
+                    token::lookup_ident(ident)
+                } else if ch.is_ascii_digit() {
+                    // Literals too large for an i64 are not valid tokens.
+                    match self.read_number(ch) {
+                        Some(value) => TokenKind::Int(value),
+                        None => TokenKind::Illegal,
+                    }
                 } else {
-                    Token::Illegal
+                    TokenKind::Illegal
                 }
             }
-            None => Token::EOF,
+            None => TokenKind::Eof,
         }
     }
 
@@ -55,7 +77,11 @@ impl<'a> Lexer<'a> {
     }
 
     fn read_char(&mut self) -> Option<char> {
-        self.input.next()
+        let ch = self.input.next();
+        if let Some(ch) = ch {
+            self.position += ch.len_utf8();
+        }
+        ch
     }
 
     fn read_identifier(&mut self, ch: char) -> String {
@@ -73,27 +99,38 @@ impl<'a> Lexer<'a> {
         ident
     }
 
-    fn read_number(&mut self, ch: char) -> i64 {
+    /// Reads up to and including the closing quote. There are no escapes.
+    fn read_string(&mut self) -> Option<String> {
+        let mut value = String::new();
+        loop {
+            match self.read_char()? {
+                '"' => return Some(value),
+                ch => value.push(ch),
+            }
+        }
+    }
+
+    fn read_number(&mut self, ch: char) -> Option<i64> {
         let mut number = String::new();
         number.push(ch);
 
         while let Some(&ch) = self.input.peek() {
-            if ch.is_digit(10) {
+            if ch.is_ascii_digit() {
                 number.push(self.read_char().unwrap())
             } else {
                 break;
             }
         }
-        number.parse().unwrap()
+        number.parse().ok()
     }
 }
 
 impl<'a> Iterator for Lexer<'a> {
-    type Item = Token;
+    type Item = TokenKind;
 
     fn next(&mut self) -> Option<<Self as Iterator>::Item> {
         let tok = self.next_token();
-        if tok == Token::EOF {
+        if tok == TokenKind::Eof {
             None
         } else {
             Some(tok)
@@ -101,6 +138,22 @@ impl<'a> Iterator for Lexer<'a> {
     }
 }
 
+/// Splits `input` into tokens with their spans, stopping before `Eof`.
+pub fn tokenize(input: &str) -> Vec<SpannedToken> {
+    let mut l = Lexer::new(input);
+    let mut tokens = Vec::new();
+    loop {
+        let kind = l.next_token();
+        if kind == TokenKind::Eof {
+            return tokens;
+        }
+        tokens.push(SpannedToken {
+            kind,
+            span: l.span(),
+        });
+    }
+}
+
 fn is_letter(ch: char) -> bool {
     ch.is_alphabetic() || ch == '_'
 }
@@ -108,19 +161,19 @@ fn is_letter(ch: char) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use token::Token;
+    use token::TokenKind;
 
     #[test]
     fn test_next_token() {
         let input = r#"let five = 5;"#;
 
-        let tests = vec![
-            Token::Let,
-            Token::Ident("five".to_string()),
-            Token::Assign,
-            Token::Int(5),
-            Token::Semicolon,
-            Token::EOF,
+        let tests = [
+            TokenKind::Let,
+            TokenKind::Ident("five".to_string()),
+            TokenKind::Assign,
+            TokenKind::Int(5),
+            TokenKind::Semicolon,
+            TokenKind::Eof,
         ];
 
         let mut l = Lexer::new(input);
@@ -131,4 +184,78 @@ mod tests {
             assert_eq!(*t, tok);
         }
     }
+
+    #[test]
+    fn test_token_span() {
+        let input = "let five =\n 55;";
+
+        let tests = [(0, 3), (4, 8), (9, 10), (12, 14), (14, 15), (15, 15)];
+
+        let mut l = Lexer::new(input);
+
+        for t in tests.iter() {
+            l.next_token();
+
+            assert_eq!(
+                Span {
+                    start: t.0,
+                    end: t.1
+                },
+                l.span()
+            );
+        }
+    }
+
+    #[test]
+    fn test_integer_overflow() {
+        let mut l = Lexer::new("9223372036854775807 9223372036854775808;");
+
+        assert_eq!(TokenKind::Int(i64::MAX), l.next_token());
+        assert_eq!(TokenKind::Illegal, l.next_token());
+        assert_eq!(TokenKind::Semicolon, l.next_token());
+    }
+
+    #[test]
+    fn test_import() {
+        let mut l = Lexer::new(r#"import "lib/math.mk" as math; math.sqrt "oops"#);
+
+        let tests = [
+            TokenKind::Import,
+            TokenKind::Str("lib/math.mk".to_string()),
+            TokenKind::As,
+            TokenKind::Ident("math".to_string()),
+            TokenKind::Semicolon,
+            TokenKind::Ident("math".to_string()),
+            TokenKind::Dot,
+            TokenKind::Ident("sqrt".to_string()),
+            TokenKind::Illegal,
+            TokenKind::Eof,
+        ];
+        for t in tests.iter() {
+            assert_eq!(*t, l.next_token());
+        }
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let tokens = tokenize("five + 5");
+
+        assert_eq!(
+            vec![
+                SpannedToken {
+                    kind: TokenKind::Ident("five".to_string()),
+                    span: Span { start: 0, end: 4 },
+                },
+                SpannedToken {
+                    kind: TokenKind::Plus,
+                    span: Span { start: 5, end: 6 },
+                },
+                SpannedToken {
+                    kind: TokenKind::Int(5),
+                    span: Span { start: 7, end: 8 },
+                },
+            ],
+            tokens
+        );
+    }
 }
@@ -1,7 +1,40 @@
+#[cfg(feature = "readline")]
+extern crate rustyline;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+
 pub mod ast;
+pub mod builtins;
+pub mod bytecode_file;
+pub mod capabilities;
+pub mod code;
+pub mod color;
+pub mod compiler;
+pub mod convert;
+pub mod diagnostic;
+pub mod environment;
 pub mod evaluator;
+pub mod ffi;
+#[cfg(feature = "readline")]
+pub mod interactive;
+pub mod interpreter;
 pub mod lexer;
+pub mod module;
 pub mod object;
+pub mod optimizer;
 pub mod parser;
 pub mod repl;
-mod token;
+#[cfg(feature = "serde")]
+pub mod serialization;
+pub mod session;
+pub mod symbol_table;
+pub mod token;
+pub mod vm;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use interpreter::Interpreter;
@@ -1,12 +1,297 @@
 extern crate monkey;
+#[cfg(feature = "serde")]
+extern crate serde_json;
 
-use monkey::repl;
+use monkey::builtins::Builtins;
+use monkey::bytecode_file;
+use monkey::capabilities::Capabilities;
+use monkey::compiler::{Bytecode, Compiler, CompilerState};
+use monkey::diagnostic::Diagnostic;
+#[cfg(feature = "readline")]
+use monkey::interactive;
+use monkey::parser::Parser;
+use monkey::session::{Engine, Session};
+use monkey::vm::VM;
+use monkey::{ast, color, lexer, optimizer, repl};
+use std::env;
+use std::fs;
 use std::io;
+#[cfg(feature = "readline")]
+use std::io::IsTerminal;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process;
+
+const USAGE: &str =
+    "usage: monkey [--engine=vm|tree] [--trace] [--optimize] [--allow-fs=DIR]... [FILE]
+       monkey tokens [--json] [FILE]
+       monkey parse [--json] [FILE]
+       monkey eval-ast FILE (with the serde feature)
+       monkey [--optimize] disasm FILE
+       monkey [--optimize] compile FILE -o OUTPUT";
 
 fn main() -> io::Result<()> {
+    let mut engine = Engine::default();
+    let mut trace = false;
+    let mut optimize = false;
+    let mut fs_roots = Vec::new();
+    let mut args = Vec::new();
+    for arg in env::args().skip(1) {
+        if let Some(name) = arg.strip_prefix("--engine=") {
+            engine = name.parse::<Engine>().unwrap_or_else(|e| usage_error(&e));
+        } else if arg == "--trace" {
+            trace = true;
+        } else if arg == "--optimize" {
+            optimize = true;
+        } else if let Some(dir) = arg.strip_prefix("--allow-fs=") {
+            fs_roots.push(PathBuf::from(dir));
+        } else {
+            args.push(arg);
+        }
+    }
+
+    let mut session = Session::new(engine);
+    session.set_trace(trace);
+    session.set_optimize(optimize);
+
+    match args.first().map(String::as_str) {
+        None => {
+            // The REPL reads stdin itself, so only scripts may read_line.
+            session.set_capabilities(capabilities(&fs_roots, false));
+            run_repl(session)
+        }
+        Some("tokens") => run_tokens(&args[1..]),
+        Some("parse") => run_parse(&args[1..]),
+        #[cfg(feature = "serde")]
+        Some("eval-ast") if args.len() == 2 => {
+            session.set_capabilities(capabilities(&fs_roots, true));
+            run_ast(&args[1], session)
+        }
+        Some("disasm") if args.len() == 2 => run_disasm(&args[1], optimize),
+        Some("compile") if args.len() == 4 && args[2] == "-o" => {
+            run_compile(&args[1], &args[3], optimize)
+        }
+        Some(path) if args.len() == 1 && !path.starts_with('-') => {
+            session.set_capabilities(capabilities(&fs_roots, true));
+            run_file(path, session)
+        }
+        Some(_) => usage_error("unexpected arguments"),
+    }
+}
+
+/// Scripts run from the command line may print, read stdin if `input` is
+/// set, and touch files below the `--allow-fs` directories.
+fn capabilities(fs_roots: &[PathBuf], input: bool) -> Capabilities {
+    let mut capabilities = Capabilities::new();
+    capabilities.set_output(Box::new(io::stdout()));
+    if input {
+        capabilities.set_input(Box::new(io::stdin().lock()));
+    }
+    for root in fs_roots {
+        capabilities.allow_fs_root(root.clone());
+    }
+    capabilities
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    process::exit(2);
+}
+
+fn run_repl(session: Session) -> io::Result<()> {
     println!("Hello, world!");
     let input = std::io::stdin();
     let output = std::io::stdout();
-    let result = repl::start(input.lock(), output.lock());
-    result
+
+    #[cfg(feature = "readline")]
+    {
+        if input.is_terminal() && output.is_terminal() {
+            return interactive::start(session).map_err(io::Error::other);
+        }
+    }
+
+    let color = color::enabled_for(&output);
+    repl::start(input.lock(), output.lock(), color, session)
+}
+
+/// Evaluates a script, or runs a file written by `monkey compile`, and prints
+/// the value of its last statement.
+fn run_file(path: &str, mut session: Session) -> io::Result<()> {
+    let bytes = fs::read(path)?;
+    if bytecode_file::is_bytecode_file(&bytes) {
+        let builtins = session.builtins().clone();
+        return run_bytecode_file(path, &bytes, builtins, session.trace());
+    }
+
+    let source = String::from_utf8(bytes).map_err(io::Error::other)?;
+    // A script named on the command line may import the files next to it.
+    let path = Path::new(path);
+    session.set_script_path(path);
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => session.allow_module_dir(dir),
+        _ => session.allow_module_dir("."),
+    }
+    match session.eval(&source) {
+        Ok(obj) => println!("{}", obj.inspect()),
+        Err(d) => {
+            eprintln!("{}", d.render(&source, color::enabled_for(&io::stderr())));
+            process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+fn run_bytecode_file(path: &str, bytes: &[u8], builtins: Builtins, trace: bool) -> io::Result<()> {
+    let bytecode = bytecode_file::decode(bytes).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e.message);
+        process::exit(1);
+    });
+    let mut vm = VM::new(bytecode);
+    vm.set_builtins(builtins);
+    if trace {
+        vm.set_trace(Box::new(io::stderr()));
+    }
+    match vm.run() {
+        Ok(()) => println!("{}", vm.result().inspect()),
+        Err(e) => {
+            eprintln!(
+                "{}",
+                Diagnostic::from(e).render("", color::enabled_for(&io::stderr()))
+            );
+            process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+/// Compiles a script and prints its instructions and constants.
+fn run_disasm(path: &str, optimize: bool) -> io::Result<()> {
+    let bytecode = compile_file(path, optimize)?;
+    print!("{}", bytecode.disassemble());
+    Ok(())
+}
+
+/// Compiles a script into a file that `monkey OUTPUT` runs without parsing.
+fn run_compile(path: &str, output: &str, optimize: bool) -> io::Result<()> {
+    let bytecode = compile_file(path, optimize)?;
+    fs::write(output, bytecode_file::encode(&bytecode))
+}
+
+/// Compiles a script, exiting with a diagnostic if it does not compile.
+fn compile_file(path: &str, optimize: bool) -> io::Result<Bytecode> {
+    let source = fs::read_to_string(path)?;
+    let mut p = Parser::new(lexer::Lexer::new(&source));
+    let compiled = match p.parse_program() {
+        Ok(mut program) => {
+            if optimize {
+                program = optimizer::optimize(program);
+            }
+            let state = CompilerState::with_builtins(&Builtins::core(Capabilities::new()));
+            let mut compiler = Compiler::new_with_state(state);
+            compiler
+                .compile(&ast::Node::Program(Box::new(program)))
+                .map(|_| compiler.bytecode())
+                .map_err(Diagnostic::from)
+        }
+        Err(e) => Err(Diagnostic::parse_error(e, &p)),
+    };
+    Ok(compiled.unwrap_or_else(|d| {
+        eprintln!("{}", d.render(&source, color::enabled_for(&io::stderr())));
+        process::exit(1);
+    }))
+}
+
+/// Dumps the token stream of FILE, or of stdin if no file is given.
+fn run_tokens(args: &[String]) -> io::Result<()> {
+    let mut json = false;
+    let mut path = None;
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            _ if path.is_none() => path = Some(arg),
+            _ => usage_error("unexpected arguments"),
+        }
+    }
+
+    let source = read_source(path)?;
+    let tokens = lexer::tokenize(&source);
+    if json {
+        let items: Vec<String> = tokens.iter().map(|t| t.to_json()).collect();
+        println!("[{}]", items.join(","));
+    } else {
+        for t in tokens {
+            println!("{}..{} {:?}", t.span.start, t.span.end, t.kind);
+        }
+    }
+    Ok(())
+}
+
+/// Prints the AST of FILE, or of stdin, as JSON with `--json` or in the
+/// REPL's `:ast` form otherwise.
+fn run_parse(args: &[String]) -> io::Result<()> {
+    let mut json = false;
+    let mut path = None;
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            _ if path.is_none() => path = Some(arg),
+            _ => usage_error("unexpected arguments"),
+        }
+    }
+
+    let source = read_source(path)?;
+    let mut p = Parser::new(lexer::Lexer::new(&source));
+    let program = p.parse_program().unwrap_or_else(|e| {
+        let d = Diagnostic::parse_error(e, &p);
+        eprintln!("{}", d.render(&source, color::enabled_for(&io::stderr())));
+        process::exit(1);
+    });
+    if json {
+        print_json(&program)
+    } else {
+        println!("{:#?}", program);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+fn print_json(program: &ast::Program) -> io::Result<()> {
+    println!("{}", serde_json::to_string_pretty(program)?);
+    Ok(())
+}
+
+#[cfg(not(feature = "serde"))]
+fn print_json(_program: &ast::Program) -> io::Result<()> {
+    usage_error("parse --json needs monkey built with the serde feature")
+}
+
+/// Evaluates an AST written by `monkey parse --json`, possibly rewritten by
+/// another tool since.
+#[cfg(feature = "serde")]
+fn run_ast(path: &str, mut session: Session) -> io::Result<()> {
+    let json = fs::read_to_string(path)?;
+    let program =
+        ast::Program::from_json(&json, monkey::parser::DEFAULT_MAX_DEPTH).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        });
+    match session.eval_program(program) {
+        Ok(obj) => println!("{}", obj.inspect()),
+        Err(d) => {
+            eprintln!("{}", d.render("", color::enabled_for(&io::stderr())));
+            process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+fn read_source(path: Option<&String>) -> io::Result<String> {
+    match path {
+        Some(path) => fs::read_to_string(path),
+        None => {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source)?;
+            Ok(source)
+        }
+    }
 }
@@ -0,0 +1,167 @@
+//! Finding and caching the modules named by `import` statements.
+
+use capabilities;
+use evaluator::EvalError;
+use object::Object;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// The modules a session can import: files below the allowed directories
+/// and virtual modules the host added from strings. Each module is evaluated
+/// once, and later imports share its bindings.
+#[derive(Debug, Default)]
+pub struct Modules {
+    sources: HashMap<PathBuf, String>,
+    roots: Vec<PathBuf>,
+    loaded: HashMap<PathBuf, Object>,
+    loading: Vec<PathBuf>,
+}
+
+impl Modules {
+    pub fn new() -> Modules {
+        Modules::default()
+    }
+
+    /// Adds a module that imports of `name` load from `source` instead of
+    /// the filesystem. `name` is resolved like an import in the top-level
+    /// program, e.g. `lib/math.mk`.
+    pub fn add_source(&mut self, name: &str, source: &str) {
+        self.sources
+            .insert(normalize(Path::new(name)), source.to_string());
+    }
+
+    /// Lets imports load files anywhere below `dir`.
+    pub fn allow_dir<P: Into<PathBuf>>(&mut self, dir: P) {
+        self.roots.push(dir.into());
+    }
+
+    /// Returns the module bound by an earlier import of `key`.
+    pub fn get(&self, key: &Path) -> Option<Object> {
+        self.loaded.get(key).cloned()
+    }
+
+    /// Returns the source of the module `key`, failing if it is neither a
+    /// virtual module nor a readable file below an allowed directory.
+    pub fn source(&self, key: &Path) -> Result<String, EvalError> {
+        if let Some(source) = self.sources.get(key) {
+            return Ok(source.clone());
+        }
+        let path = key.to_string_lossy();
+        let resolved = capabilities::check_path(&self.roots, &path)?;
+        fs::read_to_string(resolved).map_err(|e| EvalError::new(format!("{}: {}", path, e)))
+    }
+
+    /// Marks `key` as being evaluated, failing if it already is, which means
+    /// it imports itself through the modules it imports.
+    pub fn begin(&mut self, key: &Path) -> Result<(), String> {
+        if let Some(start) = self.loading.iter().position(|k| k == key) {
+            let cycle: Vec<String> = self.loading[start..]
+                .iter()
+                .chain(Some(&key.to_path_buf()))
+                .map(|k| k.display().to_string())
+                .collect();
+            return Err(format!("circular import: {}", cycle.join(" -> ")));
+        }
+        self.loading.push(key.to_path_buf());
+        Ok(())
+    }
+
+    /// Records the bindings `key` exported, or nothing if it failed.
+    pub fn finish(&mut self, key: &Path, module: Option<Object>) {
+        self.loading.retain(|k| k != key);
+        if let Some(module) = module {
+            self.loaded.insert(key.to_path_buf(), module);
+        }
+    }
+
+    /// Resolves the import `path` written in a module in `dir`. A virtual
+    /// module is found by its name alone, so it stays importable from any
+    /// directory, e.g. after `Session::set_script_path`.
+    pub fn resolve(&self, dir: &Path, path: &str) -> PathBuf {
+        let name = normalize(Path::new(path));
+        if self.sources.contains_key(&name) {
+            return name;
+        }
+        resolve(dir, path)
+    }
+
+    /// Forgets every loaded module, so the next import evaluates it again.
+    pub fn clear_loaded(&mut self) {
+        self.loaded.clear();
+    }
+}
+
+/// Resolves the import `path` written in a module in `dir`.
+pub fn resolve(dir: &Path, path: &str) -> PathBuf {
+    normalize(&dir.join(path))
+}
+
+/// Removes `.` and folds `..` into the preceding directory, without
+/// touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match out.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    out.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => out.push(".."),
+            },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_paths() {
+        let tests = vec![
+            ("", "lib/math.mk", "lib/math.mk"),
+            ("lib", "./util.mk", "lib/util.mk"),
+            ("lib/inner", "../../main.mk", "main.mk"),
+            ("lib", "../../shared.mk", "../shared.mk"),
+            ("/srv", "/etc/../lib.mk", "/lib.mk"),
+        ];
+
+        for t in tests {
+            assert_eq!(Path::new(t.2), resolve(Path::new(t.0), t.1), "{:?}", t);
+        }
+    }
+
+    #[test]
+    fn detect_cycles() {
+        let mut modules = Modules::new();
+        modules.begin(Path::new("a.mk")).unwrap();
+        modules.begin(Path::new("b.mk")).unwrap();
+        assert_eq!(
+            "circular import: a.mk -> b.mk -> a.mk",
+            modules.begin(Path::new("a.mk")).unwrap_err()
+        );
+        modules.finish(Path::new("b.mk"), Some(Object::Null));
+        let module = modules.get(Path::new("b.mk")).unwrap();
+        assert_eq!("null", module.inspect());
+    }
+
+    #[test]
+    fn resolve_virtual_modules_by_name() {
+        let mut modules = Modules::new();
+        modules.add_source("lib/math.mk", "");
+        let dir = Path::new("/srv/scripts");
+        assert_eq!(
+            Path::new("lib/math.mk"),
+            modules.resolve(dir, "./lib/math.mk")
+        );
+        assert_eq!(
+            Path::new("/srv/scripts/lib/util.mk"),
+            modules.resolve(dir, "lib/util.mk")
+        );
+    }
+}
@@ -1,12 +1,94 @@
-#[derive(Debug)]
+use builtins::Builtin;
+use std::collections::BTreeMap;
+use std::mem;
+
+#[derive(Debug, Clone)]
 pub enum Object {
+    Null,
     Int(i64),
+    Boolean(bool),
+    Str(String),
+    Array(Vec<Object>),
+    Hash(BTreeMap<HashKey, Object>),
+    Builtin(Builtin),
+}
+
+/// The values that can key a hash.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum HashKey {
+    Int(i64),
+    Boolean(bool),
+    Str(String),
 }
 
 impl Object {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Null => "NULL",
+            Object::Int(_) => "INTEGER",
+            Object::Boolean(_) => "BOOLEAN",
+            Object::Str(_) => "STRING",
+            Object::Array(_) => "ARRAY",
+            Object::Hash(_) => "HASH",
+            Object::Builtin(_) => "BUILTIN",
+        }
+    }
+
+    /// Approximate bytes this value occupies, including anything it owns on
+    /// the heap.
+    pub fn size(&self) -> usize {
+        let heap = match self {
+            Object::Str(s) => s.len(),
+            Object::Array(elements) => elements.iter().map(Object::size).sum(),
+            Object::Hash(pairs) => pairs
+                .iter()
+                .map(|(k, v)| Object::from(k.clone()).size() + v.size())
+                .sum(),
+            // Builtins are shared with the registry, so binding one costs
+            // only the handle.
+            Object::Null | Object::Int(_) | Object::Boolean(_) | Object::Builtin(_) => 0,
+        };
+        mem::size_of::<Object>() + heap
+    }
+
     pub fn inspect(&self) -> String {
         match self {
+            Object::Null => "null".to_string(),
             Object::Int(i) => i.to_string(),
+            Object::Boolean(b) => b.to_string(),
+            Object::Str(s) => s.clone(),
+            Object::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(|e| e.inspect()).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            Object::Hash(pairs) => {
+                let pairs: Vec<String> = pairs
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", Object::from(k.clone()).inspect(), v.inspect()))
+                    .collect();
+                format!("{{{}}}", pairs.join(", "))
+            }
+            Object::Builtin(b) => format!("builtin {}", b.name),
+        }
+    }
+
+    /// Returns the key this value hashes as, if it can key a hash.
+    pub fn hash_key(&self) -> Option<HashKey> {
+        match self {
+            Object::Int(i) => Some(HashKey::Int(*i)),
+            Object::Boolean(b) => Some(HashKey::Boolean(*b)),
+            Object::Str(s) => Some(HashKey::Str(s.clone())),
+            _ => None,
+        }
+    }
+}
+
+impl From<HashKey> for Object {
+    fn from(key: HashKey) -> Object {
+        match key {
+            HashKey::Int(i) => Object::Int(i),
+            HashKey::Boolean(b) => Object::Boolean(b),
+            HashKey::Str(s) => Object::Str(s),
         }
     }
 }
@@ -0,0 +1,197 @@
+use ast::*;
+use evaluator;
+use object::Object;
+
+/// Rewrites a program into an equivalent, cheaper one: constant arithmetic
+/// is folded and expression statements whose value is never used and that
+/// cannot fail are dropped. Expressions that would fail at runtime, such as
+/// a division by zero, are kept so the error is still raised when the
+/// program runs.
+pub fn optimize(program: Program) -> Program {
+    let mut statements: Vec<Statement> =
+        program.statements.into_iter().map(fold_statement).collect();
+
+    // The last statement is the value of the program, so it always stays.
+    let last = statements.pop();
+    statements.retain(|stmt| !is_pure(stmt));
+    statements.extend(last);
+
+    Program { statements }
+}
+
+fn fold_statement(stmt: Statement) -> Statement {
+    match stmt {
+        Statement::Let(stmt) => Statement::Let(Box::new(LetStatement {
+            name: stmt.name,
+            value: fold_expression(stmt.value),
+            span: stmt.span,
+        })),
+        Statement::Expression(stmt) => Statement::Expression(Box::new(ExpressionStatement {
+            expression: fold_expression(stmt.expression),
+            span: stmt.span,
+        })),
+        Statement::Import(stmt) => Statement::Import(stmt),
+    }
+}
+
+/// Folds constant subexpressions bottom up. A folded literal keeps the span
+/// of the expression it replaces.
+pub fn fold_expression(exp: Expression) -> Expression {
+    match exp {
+        Expression::Prefix(expr) => {
+            let expr = *expr;
+            let right = fold_expression(expr.right);
+            if let Expression::Integer(ref int) = right {
+                if let Ok(Object::Int(value)) =
+                    evaluator::eval_integer_prefix_expression(&expr.operator, int.value)
+                {
+                    return Expression::Integer(IntegerLiteral {
+                        value,
+                        span: expr.span,
+                    });
+                }
+            }
+            Expression::Prefix(Box::new(PrefixExpression {
+                operator: expr.operator,
+                right,
+                span: expr.span,
+            }))
+        }
+        Expression::Infix(expr) => {
+            let expr = *expr;
+            let left = fold_expression(expr.left);
+            let right = fold_expression(expr.right);
+            if let (Expression::Integer(l), Expression::Integer(r)) = (&left, &right) {
+                if let Ok(Object::Int(value)) =
+                    evaluator::eval_integer_infix_expression(&expr.operator, l.value, r.value)
+                {
+                    return Expression::Integer(IntegerLiteral {
+                        value,
+                        span: expr.span,
+                    });
+                }
+            }
+            Expression::Infix(Box::new(InfixExpression {
+                operator: expr.operator,
+                left,
+                right,
+                span: expr.span,
+            }))
+        }
+        Expression::Call(expr) => {
+            let expr = *expr;
+            Expression::Call(Box::new(CallExpression {
+                function: fold_expression(expr.function),
+                arguments: expr.arguments.into_iter().map(fold_expression).collect(),
+                span: expr.span,
+            }))
+        }
+        Expression::Member(expr) => {
+            let expr = *expr;
+            Expression::Member(Box::new(MemberExpression {
+                object: fold_expression(expr.object),
+                name: expr.name,
+                span: expr.span,
+            }))
+        }
+        exp => exp,
+    }
+}
+
+/// Reports whether removing `stmt` cannot change what a program does: it
+/// binds nothing and evaluating it can neither fail nor have effects.
+fn is_pure(stmt: &Statement) -> bool {
+    match stmt {
+        Statement::Expression(stmt) => match stmt.expression {
+            Expression::Integer(_) => true,
+            // Identifiers may be unbound and unfolded operators may fail.
+            _ => false,
+        },
+        Statement::Let(_) | Statement::Import(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use environment::Environment;
+    use lexer::Lexer;
+    use parser::Parser;
+    use token::Span;
+
+    fn optimize_str(input: &str) -> Program {
+        let mut p = Parser::new(Lexer::new(input));
+        optimize(p.parse_program().expect(input))
+    }
+
+    #[test]
+    fn fold_constants() {
+        let tests = vec![
+            ("(1104-1103)*1103;", "1103;"),
+            ("-1103+1103*2;", "1103;"),
+            ("-(1 - 2);", "1;"),
+            ("a * (2 + 3);", "(a * 5);"),
+            ("let a = 2 * 3;", "let a = 6;"),
+            ("1103 / (1 - 1);", "(1103 / 0);"),
+            ("9223372036854775807 + 1;", "(9223372036854775807 + 1);"),
+            ("f(1 + 2, g(2 * 3));", "f(3, g(6));"),
+        ];
+
+        for t in tests {
+            assert_eq!(t.1, optimize_str(t.0).to_string(), "{}", t.0);
+        }
+
+        // A folded literal points at the source it came from.
+        let program = optimize_str("f((1 + 2) * 3);");
+        match program.statements[0] {
+            Statement::Expression(ref stmt) => match stmt.expression {
+                Expression::Call(ref call) => {
+                    assert_eq!(Span { start: 2, end: 13 }, call.arguments[0].span())
+                }
+                ref exp => panic!("expected a call but got {}", exp),
+            },
+            ref stmt => panic!("expected an expression statement but got {}", stmt),
+        }
+    }
+
+    #[test]
+    fn drop_pure_statements() {
+        let tests = vec![
+            ("1; 2 * 3; a; 4;", "a;4;"),
+            ("1 / 0; 2;", "(1 / 0);2;"),
+            ("1; let a = 2;", "let a = 2;"),
+            ("1;", "1;"),
+            ("f(); 1;", "f();1;"),
+        ];
+
+        for t in tests {
+            assert_eq!(t.1, optimize_str(t.0).to_string(), "{}", t.0);
+        }
+    }
+
+    #[test]
+    fn optimized_program_evaluates_the_same() {
+        let tests = vec![
+            "(1104-1103)*1103;",
+            "let a = 2 * 3; 1; a * (4 - 2);",
+            "1103 / (1 - 1);",
+            "1; -(-9223372036854775807 - 1);",
+        ];
+
+        for t in tests {
+            let mut p = Parser::new(Lexer::new(t));
+            let program = p.parse_program().unwrap();
+            let expected = evaluator::eval(
+                &Node::Program(Box::new(optimize_str(t))),
+                &mut Environment::new(),
+            );
+            let actual =
+                evaluator::eval(&Node::Program(Box::new(program)), &mut Environment::new());
+            match (expected, actual) {
+                (Ok(e), Ok(a)) => assert_eq!(e.inspect(), a.inspect(), "{}", t),
+                (Err(e), Err(a)) => assert_eq!(e.message, a.message, "{}", t),
+                _ => panic!("{} evaluates differently after optimizing", t),
+            }
+        }
+    }
+}
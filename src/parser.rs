@@ -1,12 +1,13 @@
 use ast::*;
 use lexer::Lexer;
-use token::Token;
+use token::{Span, TokenKind};
 
 type ParseError = String;
 pub type ParseResult<T> = Result<T, ParseError>;
 type PrefixFunc = fn(parser: &mut Parser) -> ParseResult<Expression>;
 type InfixFunc = fn(parser: &mut Parser, left: Expression) -> ParseResult<Expression>;
 
+#[allow(dead_code)]
 #[derive(Eq, PartialEq, Debug, Ord, PartialOrd)]
 enum Precedence {
     Lowest,
@@ -22,23 +23,40 @@ enum Precedence {
     Prefix,
     // function
     Call,
+    // module.name
+    Member,
 }
 
 impl Precedence {
-    fn token_precedence(tok: &Token) -> Precedence {
+    fn token_precedence(tok: &TokenKind) -> Precedence {
         match tok {
-            Token::Plus | Token::Minus => Precedence::Sum,
-            Token::Asterisk | Token::Slash => Precedence::Product,
+            TokenKind::Plus | TokenKind::Minus => Precedence::Sum,
+            TokenKind::Asterisk | TokenKind::Slash => Precedence::Product,
+            TokenKind::Lparen => Precedence::Call,
+            TokenKind::Dot => Precedence::Member,
             _ => Precedence::Lowest,
         }
     }
 }
 
+/// How deeply expressions may nest by default, counting both parentheses and
+/// operators. It keeps the recursive parser, and the evaluator and compiler
+/// that walk the resulting tree, well inside a 2 MiB thread stack.
+pub const DEFAULT_MAX_DEPTH: usize = 500;
+
 pub struct Parser<'a> {
     l: Lexer<'a>,
 
-    cur_token: Token,
-    peek_token: Token,
+    cur_token: TokenKind,
+    peek_token: TokenKind,
+    cur_span: Span,
+    peek_span: Span,
+    // Where the last consumed token ended, and so the expression it closed.
+    prev_end: usize,
+
+    depth: usize,
+    max_depth: usize,
+    overflowed: bool,
 }
 
 pub fn parse(input: &str) -> Result<Node, ParseError> {
@@ -53,33 +71,53 @@ impl<'a> Parser<'a> {
     pub fn new(l: Lexer) -> Parser {
         let mut l = l;
         let cur_token = l.next_token();
+        let cur_span = l.span();
         let peek_token = l.next_token();
+        let peek_span = l.span();
         Parser {
             l,
             cur_token,
             peek_token,
+            cur_span,
+            peek_span,
+            prev_end: 0,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            overflowed: false,
         }
     }
 
+    /// Sets how deeply expressions may nest before parsing fails with a
+    /// stack overflow error.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
     fn next_token(&mut self) {
+        self.prev_end = self.cur_span.end;
         self.cur_token = self.peek_token.clone();
+        self.cur_span = self.peek_span;
         self.peek_token = self.l.next_token();
+        self.peek_span = self.l.span();
     }
 
     fn prefix_fn(&mut self) -> Option<PrefixFunc> {
         match self.cur_token {
-            Token::Int(_) => Some(Parser::parse_integer_literal),
-            Token::Minus => Some(Parser::parse_prefix_expression),
-            Token::Lparen => Some(Parser::parse_group_expression),
+            TokenKind::Ident(_) => Some(Parser::parse_identifier),
+            TokenKind::Int(_) => Some(Parser::parse_integer_literal),
+            TokenKind::Minus => Some(Parser::parse_prefix_expression),
+            TokenKind::Lparen => Some(Parser::parse_group_expression),
             _ => None,
         }
     }
 
     fn infix_fn(&mut self) -> Option<InfixFunc> {
         match self.cur_token {
-            Token::Minus | Token::Plus | Token::Asterisk | Token::Slash => {
+            TokenKind::Minus | TokenKind::Plus | TokenKind::Asterisk | TokenKind::Slash => {
                 Some(Parser::parse_infix_expression)
             }
+            TokenKind::Lparen => Some(Parser::parse_call_expression),
+            TokenKind::Dot => Some(Parser::parse_member_expression),
             _ => None,
         }
     }
@@ -87,45 +125,105 @@ impl<'a> Parser<'a> {
     pub fn parse_program(&mut self) -> ParseResult<Program> {
         let mut p = Program::new();
 
-        while self.cur_token != Token::EOF {
+        while self.cur_token != TokenKind::Eof {
             let stmt = self.parse_statement()?;
             p.statements.push(stmt);
-            self.next_token();
         }
         Ok(p)
     }
 
+    /// Reports whether the parser has consumed all of its input, e.g. to tell
+    /// an error caused by truncated input from a genuine syntax error.
+    pub fn at_eof(&self) -> bool {
+        self.cur_token == TokenKind::Eof
+    }
+
+    /// Reports whether parsing failed because expressions nested deeper than
+    /// the maximum depth.
+    pub fn overflowed(&self) -> bool {
+        self.overflowed
+    }
+
+    /// Returns the span of the current token, which is where parsing stopped
+    /// after an error.
+    pub fn span(&self) -> Span {
+        self.cur_span
+    }
+
     fn parse_statement(&mut self) -> ParseResult<Statement> {
         match self.cur_token {
-            Token::Let => self.parse_let_statement(),
+            TokenKind::Let => self.parse_let_statement(),
+            TokenKind::Import => self.parse_import_statement(),
             _ => self.parse_expression_statement(),
         }
     }
 
     fn parse_let_statement(&mut self) -> ParseResult<Statement> {
+        let start = self.cur_span.start;
         self.next_token(); // skip let
         let name = self.expect_ident()?;
 
-        self.expect_token(Token::Assign)?;
+        self.expect_token(TokenKind::Assign)?;
 
         let value = self.parse_expression(&Precedence::Lowest)?;
 
-        self.expect_token(Token::Semicolon)?;
+        let span = self.statement_span(start)?;
+        Ok(Statement::Let(Box::new(LetStatement { name, value, span })))
+    }
 
-        Ok(Statement::Let(Box::new(LetStatement { name, value })))
+    fn parse_import_statement(&mut self) -> ParseResult<Statement> {
+        let start = self.cur_span.start;
+        self.next_token(); // skip import
+        let path = match self.cur_token.clone() {
+            TokenKind::Str(path) => path,
+            tok => return Err(format!("expect module path but {}", tok)),
+        };
+        self.next_token();
+        self.expect_token(TokenKind::As)?;
+        let alias = self.expect_ident()?;
+
+        let span = self.statement_span(start)?;
+        Ok(Statement::Import(Box::new(ImportStatement {
+            path,
+            alias,
+            span,
+        })))
     }
 
     fn parse_expression_statement(&mut self) -> ParseResult<Statement> {
+        let start = self.cur_span.start;
         let expr = self.parse_expression(&Precedence::Lowest)?;
 
-        self.expect_token(Token::Semicolon)?;
-
+        let span = self.statement_span(start)?;
         Ok(Statement::Expression(Box::new(ExpressionStatement {
             expression: expr,
+            span,
         })))
     }
 
+    /// Consumes the `;` that ends a statement begun at `start` and returns
+    /// the statement's span.
+    fn statement_span(&mut self, start: usize) -> ParseResult<Span> {
+        let end = self.cur_span.end;
+        self.expect_token(TokenKind::Semicolon)?;
+        Ok(Span { start, end })
+    }
+
     fn parse_expression(&mut self, precedence: &Precedence) -> ParseResult<Expression> {
+        // Every operator applied in the loop below nests the tree one level
+        // deeper, so it counts towards the depth like a recursive call.
+        let mut nesting = 0;
+        let result = self.parse_nested_expression(precedence, &mut nesting);
+        self.depth -= nesting;
+        result
+    }
+
+    fn parse_nested_expression(
+        &mut self,
+        precedence: &Precedence,
+        nesting: &mut usize,
+    ) -> ParseResult<Expression> {
+        self.enter(nesting)?;
         let mut left;
 
         if let Some(f) = self.prefix_fn() {
@@ -134,11 +232,12 @@ impl<'a> Parser<'a> {
             return Err(format!("invalid token {:?}", self.cur_token));
         }
 
-        while self.cur_token != Token::Semicolon
+        while self.cur_token != TokenKind::Semicolon
             && *precedence < Precedence::token_precedence(&self.cur_token)
-            {
+        {
             match self.infix_fn() {
                 Some(f) => {
+                    self.enter(nesting)?;
                     left = f(self, left)?;
                 }
                 None => return Ok(left),
@@ -147,7 +246,29 @@ impl<'a> Parser<'a> {
         Ok(left)
     }
 
+    fn enter(&mut self, nesting: &mut usize) -> ParseResult<()> {
+        if self.depth >= self.max_depth {
+            self.overflowed = true;
+            return Err(format!(
+                "stack overflow: expression nested deeper than {}",
+                self.max_depth
+            ));
+        }
+        self.depth += 1;
+        *nesting += 1;
+        Ok(())
+    }
+
+    /// Returns the span from `start` through the last consumed token.
+    fn span_from(&self, start: usize) -> Span {
+        Span {
+            start,
+            end: self.prev_end,
+        }
+    }
+
     fn parse_prefix_expression(parser: &mut Parser) -> ParseResult<Expression> {
+        let start = parser.cur_span.start;
         let operator = parser.cur_token.clone();
         parser.next_token();
 
@@ -155,14 +276,16 @@ impl<'a> Parser<'a> {
         Ok(Expression::Prefix(Box::new(PrefixExpression {
             operator,
             right,
+            span: parser.span_from(start),
         })))
     }
 
     fn parse_group_expression(parser: &mut Parser) -> ParseResult<Expression> {
+        let start = parser.cur_span.start;
         parser.next_token(); // Skip Lparen
         let right = parser.parse_expression(&Precedence::Lowest)?;
-        parser.expect_token(Token::Rparen)?;
-        Ok(right)
+        parser.expect_token(TokenKind::Rparen)?;
+        Ok(right.with_span(parser.span_from(start)))
     }
 
     fn parse_infix_expression(parser: &mut Parser, left: Expression) -> ParseResult<Expression> {
@@ -170,22 +293,63 @@ impl<'a> Parser<'a> {
         parser.next_token();
 
         let right = parser.parse_expression(&Precedence::token_precedence(&operator))?;
+        let span = parser.span_from(left.span().start);
         Ok(Expression::Infix(Box::new(InfixExpression {
             operator,
             left,
             right,
+            span,
+        })))
+    }
+
+    fn parse_call_expression(parser: &mut Parser, function: Expression) -> ParseResult<Expression> {
+        parser.next_token(); // Skip Lparen
+        let mut arguments = Vec::new();
+        if parser.cur_token == TokenKind::Rparen {
+            parser.next_token();
+        } else {
+            arguments.push(parser.parse_expression(&Precedence::Lowest)?);
+            while parser.cur_token == TokenKind::Comma {
+                parser.next_token();
+                arguments.push(parser.parse_expression(&Precedence::Lowest)?);
+            }
+            parser.expect_token(TokenKind::Rparen)?;
+        }
+        let span = parser.span_from(function.span().start);
+        Ok(Expression::Call(Box::new(CallExpression {
+            function,
+            arguments,
+            span,
         })))
     }
 
+    fn parse_member_expression(parser: &mut Parser, object: Expression) -> ParseResult<Expression> {
+        parser.next_token(); // Skip Dot
+        let name = parser.expect_ident()?;
+        let span = parser.span_from(object.span().start);
+        Ok(Expression::Member(Box::new(MemberExpression {
+            object,
+            name,
+            span,
+        })))
+    }
+
+    fn parse_identifier(parser: &mut Parser) -> ParseResult<Expression> {
+        let span = parser.cur_span;
+        let name = parser.expect_ident()?;
+        Ok(Expression::Identifier(Identifier { name, span }))
+    }
+
     fn parse_integer_literal(parser: &mut Parser) -> ParseResult<Expression> {
-        if let Token::Int(value) = parser.cur_token {
+        if let TokenKind::Int(value) = parser.cur_token {
+            let span = parser.cur_span;
             parser.next_token();
-            return Ok(Expression::Integer(value));
+            return Ok(Expression::Integer(IntegerLiteral { value, span }));
         };
         Err(format!("invalid token {}", parser.cur_token))
     }
 
-    fn expect_token(&mut self, tok: Token) -> ParseResult<()> {
+    fn expect_token(&mut self, tok: TokenKind) -> ParseResult<()> {
         if tok == self.cur_token {
             self.next_token();
             return Ok(());
@@ -193,7 +357,8 @@ impl<'a> Parser<'a> {
         Err(format!("expect token {} but {}", tok, self.cur_token))
     }
 
-    fn expect_peek(&mut self, tok: Token) -> ParseResult<()> {
+    #[allow(dead_code)]
+    fn expect_peek(&mut self, tok: TokenKind) -> ParseResult<()> {
         if tok == self.peek_token {
             self.next_token();
             return Ok(());
@@ -202,7 +367,7 @@ impl<'a> Parser<'a> {
     }
 
     fn expect_ident(&mut self) -> ParseResult<String> {
-        if let Token::Ident(name) = self.cur_token.clone() {
+        if let TokenKind::Ident(name) = self.cur_token.clone() {
             self.next_token();
             return Ok(name);
         }
@@ -212,7 +377,6 @@ impl<'a> Parser<'a> {
 
 #[cfg(test)]
 mod tests {
-    use ast::*;
     use lexer::Lexer;
     use parser::*;
     use token;
@@ -223,7 +387,7 @@ mod tests {
         let l = Lexer::new(input);
         let mut p = Parser::new(l);
 
-        let expects = vec![("birthday", Expression::Integer(1103))];
+        let expects = vec![("birthday", int(1103, 15, 19))];
 
         let program = p.parse_program().unwrap();
         let mut iter = program.statements.iter();
@@ -251,76 +415,211 @@ mod tests {
         }
     }
 
-    fn setup(input: &str) -> Parser {
+    #[test]
+    fn parse_multiple_statements() {
+        let input = r#"let birthday = 1103; birthday; 2206;"#;
+        let mut p = setup(input);
+
+        let program = p.parse_program().unwrap();
+        assert_eq!(3, program.statements.len());
+    }
+
+    #[test]
+    fn parse_error_at_eof() {
+        let tests = vec![
+            ("1103 +", true),
+            ("let birthday = 1103", true),
+            ("1103 1103;", false),
+        ];
+
+        for t in tests {
+            let mut p = setup(t.0);
+            assert!(p.parse_program().is_err(), "{}", t.0);
+            assert_eq!(t.1, p.at_eof(), "{}", t.0);
+        }
+    }
+
+    #[test]
+    fn parse_nesting_limit() {
+        let depth = DEFAULT_MAX_DEPTH;
+        let tests = vec![
+            (
+                format!("{}1{};", "(".repeat(depth - 1), ")".repeat(depth - 1)),
+                true,
+            ),
+            (
+                format!("{}1{};", "(".repeat(depth), ")".repeat(depth)),
+                false,
+            ),
+            (format!("{}1;", "-".repeat(depth - 1)), true),
+            (format!("{}1;", "-".repeat(depth)), false),
+            (format!("1{};", "+1".repeat(depth - 2)), true),
+            (format!("1{};", "+1".repeat(depth - 1)), false),
+            (format!("{};", vec!["(1+1)"; depth / 2].join("*")), true),
+        ];
+
+        for t in tests {
+            let mut p = setup(&t.0);
+            match p.parse_program() {
+                Ok(_) => assert!(t.1, "{}", t.0),
+                Err(e) => {
+                    assert!(!t.1, "{}", t.0);
+                    assert_eq!("stack overflow: expression nested deeper than 500", e);
+                }
+            }
+            assert_eq!(!t.1, p.overflowed(), "{}", t.0);
+        }
+
+        let mut p = setup("((1));");
+        p.set_max_depth(2);
+        assert!(p.parse_program().is_err());
+        assert!(p.overflowed());
+
+        let mut p = setup("(1;");
+        assert!(p.parse_program().is_err());
+        assert!(!p.overflowed());
+    }
+
+    #[test]
+    fn statement_spans() {
+        let input = "let a = 1;\n  a * (2 + 3);\nimport \"m.mk\" as m ;";
+        let program = setup(input).parse_program().unwrap();
+        let spans: Vec<&str> = program
+            .statements
+            .iter()
+            .map(|stmt| {
+                let span = match stmt {
+                    Statement::Let(stmt) => stmt.span,
+                    Statement::Expression(stmt) => stmt.span,
+                    Statement::Import(stmt) => stmt.span,
+                };
+                &input[span.start..span.end]
+            })
+            .collect();
+        assert_eq!(
+            vec!["let a = 1;", "a * (2 + 3);", "import \"m.mk\" as m ;"],
+            spans
+        );
+    }
+
+    #[test]
+    fn parse_call_expression() {
+        let tests = vec![
+            ("add(1, 2 * 3);", "add(1, (2 * 3));"),
+            ("f();", "f();"),
+            ("-f(1) + g(h(2));", "((-f(1)) + g(h(2)));"),
+            ("f(1)(2);", "f(1)(2);"),
+        ];
+
+        for t in tests {
+            let mut p = setup(t.0);
+            let program = p.parse_program().expect(t.0);
+            assert_eq!(t.1, program.to_string());
+        }
+
+        for input in ["f(1, );", "f(1 2);", "f(1"] {
+            assert!(setup(input).parse_program().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn parse_import_and_member() {
+        let input = r#"import "lib/math.mk" as math; -math.square(2) + m.a.b;"#;
+        let program = setup(input).parse_program().unwrap();
+        assert_eq!(
+            r#"import "lib/math.mk" as math;((-math.square(2)) + m.a.b);"#,
+            program.to_string()
+        );
+
+        for input in ["import math;", r#"import "m.mk";"#, "m.1;", "m.;"] {
+            assert!(setup(input).parse_program().is_err(), "{}", input);
+        }
+    }
+
+    fn setup(input: &str) -> Parser<'_> {
         let l = Lexer::new(input);
         Parser::new(l)
     }
 
+    fn int(value: i64, start: usize, end: usize) -> Expression {
+        Expression::Integer(IntegerLiteral {
+            value,
+            span: Span { start, end },
+        })
+    }
+
+    fn infix(
+        operator: token::TokenKind,
+        left: Expression,
+        right: Expression,
+        end: usize,
+    ) -> Expression {
+        let start = left.span().start;
+        Expression::Infix(Box::new(InfixExpression {
+            operator,
+            left,
+            right,
+            span: Span { start, end },
+        }))
+    }
+
     #[test]
     fn parse_expression_statement() {
+        use token::TokenKind::{Asterisk, Minus, Plus};
+
         let expects = vec![
-            ("1103;", Expression::Integer(1103)),
+            ("1103;", int(1103, 0, 4)),
+            (
+                "birthday;",
+                Expression::Identifier(Identifier {
+                    name: "birthday".to_string(),
+                    span: Span { start: 0, end: 8 },
+                }),
+            ),
             (
                 "-1103;",
                 Expression::Prefix(Box::new(PrefixExpression {
-                    operator: token::Token::Minus,
-                    right: Expression::Integer(1103),
+                    operator: Minus,
+                    right: int(1103, 1, 5),
+                    span: Span { start: 0, end: 5 },
                 })),
             ),
             (
                 "2206-1103;",
-                Expression::Infix(Box::new(InfixExpression {
-                    operator: token::Token::Minus,
-                    left: Expression::Integer(2206),
-                    right: Expression::Integer(1103),
-                })),
+                infix(Minus, int(2206, 0, 4), int(1103, 5, 9), 9),
             ),
             (
                 "1103-1103+1103;",
-                Expression::Infix(Box::new(InfixExpression {
-                    operator: token::Token::Plus,
-                    right: Expression::Integer(1103),
-                    left: Expression::Infix(Box::new(InfixExpression {
-                        left: Expression::Integer(1103),
-                        operator: token::Token::Minus,
-                        right: Expression::Integer(1103),
-                    })),
-                })),
-            ),
-            (
-                "1103*2;",
-                Expression::Infix(Box::new(InfixExpression {
-                    operator: token::Token::Asterisk,
-                    left: Expression::Integer(1103),
-                    right: Expression::Integer(2),
-                })),
+                infix(
+                    Plus,
+                    infix(Minus, int(1103, 0, 4), int(1103, 5, 9), 9),
+                    int(1103, 10, 14),
+                    14,
+                ),
             ),
+            ("1103*2;", infix(Asterisk, int(1103, 0, 4), int(2, 5, 6), 6)),
             (
                 "-1103-1103*1103;",
-                Expression::Infix(Box::new(InfixExpression {
-                    operator: token::Token::Minus,
-                    left: Expression::Prefix(Box::new(PrefixExpression {
-                        operator: token::Token::Minus,
-                        right: Expression::Integer(1103),
-                    })),
-                    right: Expression::Infix(Box::new(InfixExpression {
-                        left: Expression::Integer(1103),
-                        operator: token::Token::Asterisk,
-                        right: Expression::Integer(1103),
+                infix(
+                    Minus,
+                    Expression::Prefix(Box::new(PrefixExpression {
+                        operator: Minus,
+                        right: int(1103, 1, 5),
+                        span: Span { start: 0, end: 5 },
                     })),
-                })),
+                    infix(Asterisk, int(1103, 6, 10), int(1103, 11, 15), 15),
+                    15,
+                ),
             ),
             (
                 "1103-(1103+1103);",
-                Expression::Infix(Box::new(InfixExpression {
-                    operator: token::Token::Minus,
-                    left: Expression::Integer(1103),
-                    right: Expression::Infix(Box::new(InfixExpression {
-                        left: Expression::Integer(1103),
-                        operator: token::Token::Plus,
-                        right: Expression::Integer(1103),
-                    })),
-                })),
+                infix(
+                    Minus,
+                    int(1103, 0, 4),
+                    infix(Plus, int(1103, 6, 10), int(1103, 11, 15), 15)
+                        .with_span(Span { start: 5, end: 16 }),
+                    16,
+                ),
             ),
         ];
 
@@ -337,4 +636,42 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn expression_spans() {
+        let input = "let x = -f(a, (2)).b + 3;";
+        let program = setup(input).parse_program().unwrap();
+        let mut spans = Vec::new();
+        let mut exp = match program.statements[0] {
+            Statement::Let(ref l) => &l.value,
+            ref stmt => panic!("expected let statement but got {:?}", stmt),
+        };
+        loop {
+            spans.push(&input[exp.span().start..exp.span().end]);
+            exp = match exp {
+                Expression::Infix(e) => &e.left,
+                Expression::Prefix(e) => &e.right,
+                Expression::Member(e) => &e.object,
+                Expression::Call(e) => {
+                    for arg in &e.arguments {
+                        spans.push(&input[arg.span().start..arg.span().end]);
+                    }
+                    &e.function
+                }
+                _ => break,
+            };
+        }
+        assert_eq!(
+            vec![
+                "-f(a, (2)).b + 3",
+                "-f(a, (2)).b",
+                "f(a, (2)).b",
+                "f(a, (2))",
+                "a",
+                "(2)",
+                "f"
+            ],
+            spans
+        );
+    }
 }
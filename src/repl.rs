@@ -1,21 +1,244 @@
-use evaluator;
-use parser;
+use diagnostic::Diagnostic;
+use lexer::Lexer;
+use parser::Parser;
+use session::Session;
+use std::fs;
 use std::io;
+use token::TokenKind;
 
-pub fn start<R: io::BufRead, W: io::Write>(mut r: R, mut w: W) -> io::Result<()> {
+pub const PROMPT: &str = "> ";
+pub const CONTINUATION_PROMPT: &str = ".. ";
+
+/// Runs the REPL over `r` and `w`, evaluating input in `session`. Errors are
+/// rendered with ANSI colors if `color` is set.
+pub fn start<R: io::BufRead, W: io::Write>(
+    mut r: R,
+    mut w: W,
+    color: bool,
+    mut session: Session,
+) -> io::Result<()> {
+    let mut input = String::new();
     loop {
-        let _i = w.write("> ".as_bytes());
-        let _s = w.flush();
+        let prompt = if input.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        };
+        w.write_all(prompt.as_bytes())?;
+        w.flush()?;
+
         let mut line = String::new();
-        let _result = r.read_line(&mut line);
+        if r.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
 
-        let obj = match parser::parse(line.as_str()) {
-            Ok(node) => evaluator::eval(&node),
-            Err(e) => Err(evaluator::EvalError { message: e }),
-        };
-        match obj {
-            Ok(o) => println!("{:?}", o.inspect()),
-            Err(e) => println!("{:?}", e),
+        // A blank line cancels a pending multi-line input.
+        if line.trim().is_empty() {
+            input.clear();
+            continue;
+        }
+
+        if input.is_empty() && is_command(&line) {
+            writeln!(w, "{}", run_command(line.trim(), &mut session, color))?;
+            continue;
+        }
+
+        input.push_str(&line);
+        if is_incomplete(&input) {
+            continue;
+        }
+
+        writeln!(w, "{}", eval_input(&input, &mut session, color))?;
+        input.clear();
+    }
+}
+
+/// Parses and evaluates one complete REPL input, returning the text to print.
+pub fn eval_input(input: &str, session: &mut Session, color: bool) -> String {
+    match session.eval(input) {
+        Ok(o) => format!("{:?}", o.inspect()),
+        Err(d) => d.render(input, color),
+    }
+}
+
+/// Reports whether a REPL line is a meta-command such as `:env`.
+pub fn is_command(line: &str) -> bool {
+    line.trim_start().starts_with(':')
+}
+
+/// Runs a REPL meta-command, returning the text to print.
+///
+/// * `:tokens <code>` dumps the lexer token stream
+/// * `:ast <code>` pretty-prints the parsed program
+/// * `:env` lists the session bindings
+/// * `:load <file>` evaluates a file into the session
+/// * `:reset` clears the session bindings
+/// * `:type <expr>` shows the kind of object an expression evaluates to,
+///   without binding anything or doing I/O
+pub fn run_command(line: &str, session: &mut Session, color: bool) -> String {
+    let (command, arg) = match line.find(char::is_whitespace) {
+        Some(i) => (&line[..i], line[i..].trim()),
+        None => (line, ""),
+    };
+
+    match command {
+        ":tokens" => Lexer::new(arg)
+            .map(|tok| format!("{:?}", tok))
+            .collect::<Vec<String>>()
+            .join("\n"),
+        ":ast" => {
+            let mut p = Parser::new(Lexer::new(arg));
+            match p.parse_program() {
+                Ok(program) => format!("{:#?}", program),
+                Err(e) => Diagnostic::parse_error(e, &p).render(arg, color),
+            }
         }
+        ":env" => session
+            .bindings()
+            .iter()
+            .map(|(name, value)| format!("{} = {}", name, value.inspect()))
+            .collect::<Vec<String>>()
+            .join("\n"),
+        ":load" => match fs::read_to_string(arg) {
+            Ok(source) => eval_input(&source, session, color),
+            Err(e) => format!("cannot load {}: {}", arg, e),
+        },
+        ":reset" => {
+            session.reset();
+            "environment cleared".to_string()
+        }
+        ":type" => match session.eval_expression(arg) {
+            Ok(o) => o.type_name().to_string(),
+            Err(d) => d.render(arg, color),
+        },
+        _ => format!("unknown command {}", command),
+    }
+}
+
+/// Reports whether `input` needs more lines before it can be evaluated: it
+/// has unclosed braces or parens, or the parser ran out of tokens.
+pub fn is_incomplete(input: &str) -> bool {
+    let mut depth = 0;
+    for tok in Lexer::new(input) {
+        match tok {
+            TokenKind::Lbrace | TokenKind::Lparen => depth += 1,
+            TokenKind::Rbrace | TokenKind::Rparen => depth -= 1,
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        return true;
+    }
+
+    let mut p = Parser::new(Lexer::new(input));
+    p.parse_program().is_err() && p.at_eof()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use session::Engine;
+
+    fn run(input: &str) -> String {
+        let mut output = Vec::new();
+        start(
+            input.as_bytes(),
+            &mut output,
+            false,
+            Session::new(Engine::Tree),
+        )
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn incomplete_input() {
+        let tests = vec![
+            ("1103;", false),
+            ("(1103", true),
+            ("(1103 -\n1)", true),
+            ("1103 -", true),
+            ("let birthday = 1103", true),
+            ("1103 1103;", false),
+            ("1103);", false),
+        ];
+
+        for t in tests {
+            assert_eq!(t.1, is_incomplete(t.0), "{}", t.0);
+        }
+    }
+
+    #[test]
+    fn continuation_prompt() {
+        assert_eq!("> .. .. \"1102\"\n> ", run("(1103\n-\n1);\n"));
+    }
+
+    #[test]
+    fn bindings_persist_between_inputs() {
+        assert_eq!("> \"1103\"\n> \"2206\"\n> ", run("let a = 1103;\na * 2;\n"));
+    }
+
+    #[test]
+    fn meta_commands() {
+        let mut session = Session::new(Engine::Tree);
+        let output = ::interpreter::CapturedOutput::new();
+        session.set_output(Box::new(output.clone()));
+        eval_input("let b = 2; let a = 1;", &mut session, false);
+
+        assert_eq!(
+            "Let\nIdent(\"five\")\nAssign\nInt(5)\nSemicolon",
+            run_command(":tokens let five = 5;", &mut session, false)
+        );
+        assert!(run_command(":ast -1;", &mut session, false).contains("Prefix("));
+        assert_eq!("a = 1\nb = 2", run_command(":env", &mut session, false));
+        assert_eq!("INTEGER", run_command(":type a + b;", &mut session, false));
+        assert_eq!(
+            "error: expected a single expression",
+            run_command(":type let zz = 3;", &mut session, false)
+        );
+        assert_eq!("a = 1\nb = 2", run_command(":env", &mut session, false));
+        assert_eq!("NULL", run_command(":type puts(1);", &mut session, false));
+        assert_eq!("", output.contents());
+        assert_eq!(
+            "unknown command :foo",
+            run_command(":foo", &mut session, false)
+        );
+
+        run_command(":reset", &mut session, false);
+        assert_eq!("", run_command(":env", &mut session, false));
+    }
+
+    #[test]
+    fn load_command() {
+        let path = std::env::temp_dir().join("monkey_repl_load_command.mk");
+        fs::write(&path, "let birthday = 1103;\nbirthday * 2;\n").unwrap();
+        let mut session = Session::new(Engine::Tree);
+
+        let output = run_command(&format!(":load {}", path.display()), &mut session, false);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!("\"2206\"", output);
+        assert_eq!("birthday = 1103", run_command(":env", &mut session, false));
+    }
+
+    #[test]
+    fn errors_are_rendered_as_diagnostics() {
+        assert_eq!(
+            "> error: identifier not found: a\n --> 1:1\n  |\n1 | a;\n  | ^\n> ",
+            run("a;\n")
+        );
+        assert_eq!(
+            "> error: division by zero\n --> 1:5\n  |\n1 | 2 + 1 / 0;\n  |     ^^^^^\n> ",
+            run("2 + 1 / 0;\n")
+        );
+        assert_eq!(
+            "> error: invalid token Rparen\n --> 1:5\n  |\n1 | 1 + );\n  |     ^\n> ",
+            run("1 + );\n")
+        );
+    }
+
+    #[test]
+    fn blank_line_cancels_input() {
+        assert_eq!("> .. > \"1103\"\n> ", run("(1\n\n1103;\n"));
     }
 }
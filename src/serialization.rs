@@ -0,0 +1,803 @@
+use object::{HashKey, Object};
+use serde::de::value::BorrowedStrDeserializer;
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde::ser::{self, SerializeMap, SerializeSeq, Serializer};
+use serde::{forward_to_deserialize_any, Deserialize, Serialize};
+use std::collections::{btree_map, BTreeMap};
+use std::fmt;
+use std::slice;
+
+#[derive(Debug)]
+pub struct SerdeError {
+    pub message: String,
+}
+
+impl fmt::Display for SerdeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for SerdeError {}
+
+impl ser::Error for SerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> SerdeError {
+        SerdeError {
+            message: msg.to_string(),
+        }
+    }
+}
+
+impl de::Error for SerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> SerdeError {
+        SerdeError {
+            message: msg.to_string(),
+        }
+    }
+}
+
+/// Converts any serializable Rust value into an object. Map keys keep their
+/// type, so a `HashMap<i64, _>` becomes a hash with integer keys. Floats have
+/// no Monkey counterpart and fail.
+pub fn to_object<T: Serialize>(value: &T) -> Result<Object, SerdeError> {
+    value.serialize(ObjectSerializer)
+}
+
+/// Converts an object into any deserializable Rust value. Builtins have no
+/// Rust counterpart and fail.
+pub fn from_object<T: DeserializeOwned>(obj: &Object) -> Result<T, SerdeError> {
+    T::deserialize(ObjectDeserializer(obj))
+}
+
+/// Builds an object from a Rust value. Enum variants with data become a
+/// hash from the variant name to the data, as in serde's JSON format.
+struct ObjectSerializer;
+
+/// Wraps the data of an enum variant, if any, in a hash keyed by its name.
+fn wrap_variant(variant: Option<&'static str>, obj: Object) -> Object {
+    match variant {
+        Some(name) => Object::Hash(
+            Some((HashKey::Str(name.to_string()), obj))
+                .into_iter()
+                .collect(),
+        ),
+        None => obj,
+    }
+}
+
+impl Serializer for ObjectSerializer {
+    type Ok = Object;
+    type Error = SerdeError;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Object, SerdeError> {
+        Ok(Object::Boolean(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Object, SerdeError> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Object, SerdeError> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Object, SerdeError> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Object, SerdeError> {
+        Ok(Object::Int(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Object, SerdeError> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Object, SerdeError> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Object, SerdeError> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Object, SerdeError> {
+        if v > i64::MAX as u64 {
+            return Err(ser::Error::custom(format!("integer {} out of range", v)));
+        }
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Object, SerdeError> {
+        self.serialize_f64(f64::from(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Object, SerdeError> {
+        Err(ser::Error::custom(format!("float {} is not supported", v)))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Object, SerdeError> {
+        Ok(Object::Str(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Object, SerdeError> {
+        Ok(Object::Str(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Object, SerdeError> {
+        Ok(Object::Array(
+            v.iter().map(|b| Object::Int(i64::from(*b))).collect(),
+        ))
+    }
+
+    fn serialize_none(self) -> Result<Object, SerdeError> {
+        Ok(Object::Null)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Object, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Object, SerdeError> {
+        Ok(Object::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Object, SerdeError> {
+        Ok(Object::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Object, SerdeError> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Object, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Object, SerdeError> {
+        Ok(wrap_variant(Some(variant), value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, SerdeError> {
+        Ok(SeqSerializer {
+            elements: Vec::with_capacity(len.unwrap_or(0)),
+            variant: None,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, SerdeError> {
+        Ok(SeqSerializer {
+            elements: Vec::with_capacity(len),
+            variant: Some(variant),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer, SerdeError> {
+        Ok(MapSerializer {
+            pairs: BTreeMap::new(),
+            key: None,
+            variant: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<MapSerializer, SerdeError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<MapSerializer, SerdeError> {
+        Ok(MapSerializer {
+            pairs: BTreeMap::new(),
+            key: None,
+            variant: Some(variant),
+        })
+    }
+}
+
+struct SeqSerializer {
+    elements: Vec<Object>,
+    variant: Option<&'static str>,
+}
+
+impl SerializeSeq for SeqSerializer {
+    type Ok = Object;
+    type Error = SerdeError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.elements.push(value.serialize(ObjectSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Object, SerdeError> {
+        Ok(wrap_variant(self.variant, Object::Array(self.elements)))
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Object;
+    type Error = SerdeError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SerdeError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Object, SerdeError> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Object;
+    type Error = SerdeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SerdeError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Object, SerdeError> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = Object;
+    type Error = SerdeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SerdeError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Object, SerdeError> {
+        SerializeSeq::end(self)
+    }
+}
+
+struct MapSerializer {
+    pairs: BTreeMap<HashKey, Object>,
+    // The key of the entry whose value comes next.
+    key: Option<HashKey>,
+    variant: Option<&'static str>,
+}
+
+impl SerializeMap for MapSerializer {
+    type Ok = Object;
+    type Error = SerdeError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), SerdeError> {
+        let key = key.serialize(ObjectSerializer)?;
+        match key.hash_key() {
+            Some(k) => {
+                self.key = Some(k);
+                Ok(())
+            }
+            None => Err(ser::Error::custom(format!(
+                "unusable as hash key: {}",
+                key.type_name()
+            ))),
+        }
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| <SerdeError as ser::Error>::custom("map value without a key"))?;
+        self.pairs.insert(key, value.serialize(ObjectSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Object, SerdeError> {
+        Ok(wrap_variant(self.variant, Object::Hash(self.pairs)))
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Object;
+    type Error = SerdeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        let value = value.serialize(ObjectSerializer)?;
+        self.pairs.insert(HashKey::Str(key.to_string()), value);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Object, SerdeError> {
+        SerializeMap::end(self)
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer {
+    type Ok = Object;
+    type Error = SerdeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        ser::SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<Object, SerdeError> {
+        SerializeMap::end(self)
+    }
+}
+
+/// Reads a Rust value out of an object, the reverse of `ObjectSerializer`.
+struct ObjectDeserializer<'a>(&'a Object);
+
+impl<'de> Deserializer<'de> for ObjectDeserializer<'de> {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            Object::Null => visitor.visit_unit(),
+            Object::Int(i) => visitor.visit_i64(*i),
+            Object::Boolean(b) => visitor.visit_bool(*b),
+            Object::Str(s) => visitor.visit_borrowed_str(s),
+            Object::Array(elements) => visitor.visit_seq(SeqDeserializer {
+                elements: elements.iter(),
+            }),
+            Object::Hash(pairs) => visitor.visit_map(MapDeserializer {
+                pairs: pairs.iter(),
+                value: None,
+            }),
+            Object::Builtin(b) => Err(de::Error::custom(format!(
+                "cannot deserialize builtin {}",
+                b.name
+            ))),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            Object::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            Object::Str(variant) => visitor.visit_enum(EnumDeserializer {
+                variant,
+                value: None,
+            }),
+            Object::Hash(pairs) if pairs.len() == 1 => match pairs.iter().next() {
+                Some((HashKey::Str(variant), value)) => visitor.visit_enum(EnumDeserializer {
+                    variant,
+                    value: Some(value),
+                }),
+                _ => Err(de::Error::custom("enum variant names must be strings")),
+            },
+            obj => Err(de::Error::custom(format!(
+                "expected an enum variant, found {}",
+                obj.type_name()
+            ))),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct SeqDeserializer<'a> {
+    elements: slice::Iter<'a, Object>,
+}
+
+impl<'de> SeqAccess<'de> for SeqDeserializer<'de> {
+    type Error = SerdeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, SerdeError> {
+        match self.elements.next() {
+            Some(e) => seed.deserialize(ObjectDeserializer(e)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.elements.len())
+    }
+}
+
+struct MapDeserializer<'a> {
+    pairs: btree_map::Iter<'a, HashKey, Object>,
+    // The value of the entry whose key was read last.
+    value: Option<&'a Object>,
+}
+
+impl<'de> MapAccess<'de> for MapDeserializer<'de> {
+    type Error = SerdeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, SerdeError> {
+        match self.pairs.next() {
+            Some((k, v)) => {
+                self.value = Some(v);
+                seed.deserialize(KeyDeserializer(k)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, SerdeError> {
+        match self.value.take() {
+            Some(v) => seed.deserialize(ObjectDeserializer(v)),
+            None => Err(de::Error::custom("map value without a key")),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.pairs.len())
+    }
+}
+
+struct KeyDeserializer<'a>(&'a HashKey);
+
+impl<'de> Deserializer<'de> for KeyDeserializer<'de> {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            HashKey::Int(i) => visitor.visit_i64(*i),
+            HashKey::Boolean(b) => visitor.visit_bool(*b),
+            HashKey::Str(s) => visitor.visit_borrowed_str(s),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// An enum variant: a bare name for unit variants, or a name and its data.
+struct EnumDeserializer<'a> {
+    variant: &'a str,
+    value: Option<&'a Object>,
+}
+
+impl<'de> EnumAccess<'de> for EnumDeserializer<'de> {
+    type Error = SerdeError;
+    type Variant = VariantDeserializer<'de>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, VariantDeserializer<'de>), SerdeError> {
+        let variant = seed.deserialize(BorrowedStrDeserializer::new(self.variant))?;
+        Ok((variant, VariantDeserializer { value: self.value }))
+    }
+}
+
+struct VariantDeserializer<'a> {
+    value: Option<&'a Object>,
+}
+
+impl<'a> VariantDeserializer<'a> {
+    fn data(self) -> Result<ObjectDeserializer<'a>, SerdeError> {
+        self.value
+            .map(ObjectDeserializer)
+            .ok_or_else(|| de::Error::custom("enum variant has no data"))
+    }
+}
+
+impl<'de> VariantAccess<'de> for VariantDeserializer<'de> {
+    type Error = SerdeError;
+
+    fn unit_variant(self) -> Result<(), SerdeError> {
+        match self.value {
+            None | Some(Object::Null) => Ok(()),
+            Some(obj) => Err(de::Error::custom(format!(
+                "unit variant has data: {}",
+                obj.inspect()
+            ))),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, SerdeError> {
+        seed.deserialize(self.data()?)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        self.data()?.deserialize_seq(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        self.data()?.deserialize_map(visitor)
+    }
+}
+
+impl Serialize for Object {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Object::Null => serializer.serialize_unit(),
+            Object::Int(i) => serializer.serialize_i64(*i),
+            Object::Boolean(b) => serializer.serialize_bool(*b),
+            Object::Str(s) => serializer.serialize_str(s),
+            Object::Array(elements) => {
+                let mut seq = serializer.serialize_seq(Some(elements.len()))?;
+                for e in elements {
+                    seq.serialize_element(e)?;
+                }
+                seq.end()
+            }
+            Object::Hash(pairs) => {
+                let mut map = serializer.serialize_map(Some(pairs.len()))?;
+                for (k, v) in pairs {
+                    map.serialize_entry(k, v)?;
+                }
+                map.end()
+            }
+            Object::Builtin(b) => Err(ser::Error::custom(format!(
+                "cannot serialize builtin {}",
+                b.name
+            ))),
+        }
+    }
+}
+
+impl Serialize for HashKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            HashKey::Int(i) => serializer.serialize_i64(*i),
+            HashKey::Boolean(b) => serializer.serialize_bool(*b),
+            HashKey::Str(s) => serializer.serialize_str(s),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Object {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Object, D::Error> {
+        deserializer.deserialize_any(ObjectVisitor)
+    }
+}
+
+struct ObjectVisitor;
+
+impl<'de> Visitor<'de> for ObjectVisitor {
+    type Value = Object;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "null, an integer, a boolean, a string, an array or a map"
+        )
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Object, E> {
+        Ok(Object::Null)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Object, E> {
+        Ok(Object::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Object, D::Error> {
+        Object::deserialize(deserializer)
+    }
+
+    fn visit_bool<E: de::Error>(self, b: bool) -> Result<Object, E> {
+        Ok(Object::Boolean(b))
+    }
+
+    fn visit_i64<E: de::Error>(self, i: i64) -> Result<Object, E> {
+        Ok(Object::Int(i))
+    }
+
+    fn visit_u64<E: de::Error>(self, u: u64) -> Result<Object, E> {
+        if u > i64::MAX as u64 {
+            return Err(E::custom(format!("integer {} out of range", u)));
+        }
+        Ok(Object::Int(u as i64))
+    }
+
+    fn visit_f64<E: de::Error>(self, f: f64) -> Result<Object, E> {
+        Err(E::custom(format!("float {} is not supported", f)))
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Object, E> {
+        Ok(Object::Str(s.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, s: String) -> Result<Object, E> {
+        Ok(Object::Str(s))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Object, A::Error> {
+        let mut elements = Vec::new();
+        while let Some(e) = seq.next_element()? {
+            elements.push(e);
+        }
+        Ok(Object::Array(elements))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Object, A::Error> {
+        let mut pairs = BTreeMap::new();
+        while let Some((k, v)) = map.next_entry::<Object, Object>()? {
+            let key = k.hash_key().ok_or_else(|| {
+                de::Error::custom(format!("unusable as hash key: {}", k.type_name()))
+            })?;
+            pairs.insert(key, v);
+        }
+        Ok(Object::Hash(pairs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use builtins::Builtins;
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Config {
+        name: String,
+        retries: i64,
+        verbose: bool,
+        tags: Vec<String>,
+        parent: Option<String>,
+    }
+
+    #[test]
+    fn round_trip_struct() {
+        let config = Config {
+            name: "monkey".to_string(),
+            retries: 3,
+            verbose: true,
+            tags: vec!["a".to_string()],
+            parent: None,
+        };
+        let obj = to_object(&config).unwrap();
+        assert_eq!(
+            "{name: monkey, parent: null, retries: 3, tags: [a], verbose: true}",
+            obj.inspect()
+        );
+        assert_eq!(config, from_object::<Config>(&obj).unwrap());
+    }
+
+    #[test]
+    fn integer_keys() {
+        let mut map = HashMap::new();
+        map.insert(1i64, "one".to_string());
+        let obj = to_object(&map).unwrap();
+        match obj {
+            Object::Hash(ref pairs) => {
+                assert_eq!(vec![&HashKey::Int(1)], pairs.keys().collect::<Vec<_>>())
+            }
+            _ => panic!("not a hash: {}", obj.inspect()),
+        }
+        assert_eq!(map, from_object::<HashMap<i64, String>>(&obj).unwrap());
+    }
+
+    #[test]
+    fn unsupported_values() {
+        let err = to_object(&1.5f64).unwrap_err();
+        assert_eq!("float 1.5 is not supported", err.message);
+        let err = to_object(&vec![f64::NAN]).unwrap_err();
+        assert_eq!("float NaN is not supported", err.message);
+        let err = to_object(&u64::MAX).unwrap_err();
+        assert_eq!("integer 18446744073709551615 out of range", err.message);
+
+        let mut builtins = Builtins::new();
+        builtins.register("f", 0, "", |_| Ok(Object::Null));
+        let builtin = Object::Builtin(builtins.get("f").unwrap().clone());
+        let err = from_object::<Vec<i64>>(&Object::Array(vec![builtin])).unwrap_err();
+        assert_eq!("cannot deserialize builtin f", err.message);
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Shape {
+        Point,
+        Circle(i64),
+        Line(i64, i64),
+        Rect { w: i64, h: i64 },
+    }
+
+    #[test]
+    fn round_trip_enums() {
+        let tests = vec![
+            (Shape::Point, "Point"),
+            (Shape::Circle(3), "{Circle: 3}"),
+            (Shape::Line(1, 2), "{Line: [1, 2]}"),
+            (Shape::Rect { w: 1, h: 2 }, "{Rect: {h: 2, w: 1}}"),
+        ];
+
+        for t in tests {
+            let obj = to_object(&t.0).unwrap();
+            assert_eq!(t.1, obj.inspect());
+            assert_eq!(t.0, from_object::<Shape>(&obj).unwrap());
+        }
+    }
+}
@@ -0,0 +1,774 @@
+use ast::{Node, Program, Statement};
+use builtins;
+use builtins::Builtins;
+use capabilities::Capabilities;
+use compiler::{Compiler, CompilerState};
+use convert::NativeFunction;
+use diagnostic::Diagnostic;
+use environment::Environment;
+use evaluator;
+use evaluator::{Budget, ErrorKind, EvalError, EvalResult, Limits};
+use lexer::Lexer;
+use module::Modules;
+use object::{HashKey, Object};
+use optimizer;
+use parser;
+use parser::Parser;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use symbol_table::SymbolScope;
+use vm::VM;
+
+/// Selects how a session executes programs.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub enum Engine {
+    /// Walks the AST with `evaluator::eval`.
+    #[default]
+    Tree,
+    /// Compiles to bytecode and runs it on the `vm::VM`.
+    Vm,
+}
+
+impl FromStr for Engine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Engine, String> {
+        match s {
+            "tree" => Ok(Engine::Tree),
+            "vm" => Ok(Engine::Vm),
+            _ => Err(format!("unknown engine {}, expected vm or tree", s)),
+        }
+    }
+}
+
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Engine::Tree => write!(f, "tree"),
+            Engine::Vm => write!(f, "vm"),
+        }
+    }
+}
+
+/// Bindings that persist between the programs evaluated in one session, for
+/// either engine.
+#[derive(Debug)]
+pub struct Session {
+    engine: Engine,
+    env: Environment,
+    compiler_state: CompilerState,
+    globals: Vec<Option<Object>>,
+    trace: bool,
+    optimize: bool,
+    limits: Limits,
+    max_parse_depth: usize,
+    builtins: Builtins,
+    modules: Modules,
+    dir: PathBuf,
+}
+
+impl Default for Session {
+    fn default() -> Session {
+        let builtins = Builtins::core(Capabilities::default());
+        Session {
+            engine: Engine::default(),
+            env: Environment::default(),
+            compiler_state: CompilerState::with_builtins(&builtins),
+            globals: Vec::new(),
+            trace: false,
+            optimize: false,
+            limits: Limits::default(),
+            max_parse_depth: parser::DEFAULT_MAX_DEPTH,
+            builtins,
+            modules: Modules::new(),
+            dir: PathBuf::new(),
+        }
+    }
+}
+
+impl Session {
+    pub fn new(engine: Engine) -> Session {
+        Session {
+            engine,
+            ..Session::default()
+        }
+    }
+
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
+
+    pub fn trace(&self) -> bool {
+        self.trace
+    }
+
+    /// Makes the VM engine log each executed instruction to stderr.
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    /// Runs programs through `optimizer::optimize` before executing them.
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    /// Sets the limits each evaluation runs under. The VM honours the fuel,
+    /// timeout and memory cap; nesting depth only applies to the tree engine.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Sets how deeply expressions may nest in parsed source.
+    pub fn set_max_parse_depth(&mut self, max_depth: usize) {
+        self.max_parse_depth = max_depth;
+    }
+
+    /// Adds a module that `import "name"` loads from `source`.
+    pub fn add_module(&mut self, name: &str, source: &str) {
+        self.modules.add_source(name, source);
+    }
+
+    /// Lets imports load files anywhere below `dir`.
+    pub fn allow_module_dir<P: Into<PathBuf>>(&mut self, dir: P) {
+        self.modules.allow_dir(dir);
+    }
+
+    /// Resolves imports in later programs against the directory of the
+    /// script at `path`. Loading files from there still takes
+    /// `allow_module_dir`.
+    pub fn set_script_path(&mut self, path: &Path) {
+        self.dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    }
+
+    /// Grants scripts the I/O in `capabilities`, replacing what was granted
+    /// before. Sessions start with none.
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        for builtin in Builtins::core(capabilities).iter() {
+            let index = self.builtins.insert(builtin.clone());
+            self.define_builtin(index, &builtin.name);
+        }
+    }
+
+    /// Sends the output of `puts` to `output`, leaving other capabilities
+    /// as they are.
+    pub fn set_output(&mut self, output: Box<dyn io::Write>) {
+        self.register_builtin("puts", 1, builtins::PUTS_DOC, builtins::puts(Some(output)));
+    }
+
+    /// Makes a Rust function callable from scripts as `name`. Bindings of the
+    /// same name shadow it, as they would a core builtin.
+    pub fn register_builtin<F>(&mut self, name: &str, arity: usize, doc: &str, function: F)
+    where
+        F: Fn(&[Object]) -> EvalResult + 'static,
+    {
+        let index = self.builtins.register(name, arity, doc, function);
+        self.define_builtin(index, name);
+    }
+
+    /// Makes a typed Rust function callable from scripts as `name`, see
+    /// `convert::NativeFunction`.
+    pub fn register_fn<F, Args>(&mut self, name: &str, doc: &str, function: F)
+    where
+        F: NativeFunction<Args>,
+    {
+        let index = self.builtins.register_fn(name, doc, function);
+        self.define_builtin(index, name);
+    }
+
+    pub fn builtins(&self) -> &Builtins {
+        &self.builtins
+    }
+
+    fn define_builtin(&mut self, index: usize, name: &str) {
+        let table = &mut self.compiler_state.symbol_table;
+        match table.resolve(name) {
+            Some(ref symbol) if symbol.scope == SymbolScope::Global => {}
+            _ => {
+                table.define_builtin(index, name);
+            }
+        }
+    }
+
+    /// Parses and evaluates `source`, reporting failures as diagnostics.
+    pub fn eval(&mut self, source: &str) -> Result<Object, Diagnostic> {
+        let mut budget = Budget::new(&self.limits);
+        self.eval_with_budget(source, &mut budget)
+    }
+
+    fn eval_with_budget(
+        &mut self,
+        source: &str,
+        budget: &mut Budget,
+    ) -> Result<Object, Diagnostic> {
+        let mut p = Parser::new(Lexer::new(source));
+        p.set_max_depth(self.max_parse_depth);
+        let program = p
+            .parse_program()
+            .map_err(|e| Diagnostic::parse_error(e, &p))?;
+        self.run(program, budget)
+    }
+
+    /// Evaluates the single expression in `source` without binding anything
+    /// or doing I/O, e.g. for the REPL's `:type`. `puts` output is discarded
+    /// and the other I/O builtins see no capabilities.
+    pub fn eval_expression(&self, source: &str) -> Result<Object, Diagnostic> {
+        let mut p = Parser::new(Lexer::new(source));
+        p.set_max_depth(self.max_parse_depth);
+        let mut program = p
+            .parse_program()
+            .map_err(|e| Diagnostic::parse_error(e, &p))?;
+        let expression = match program.statements.pop() {
+            Some(Statement::Expression(stmt)) if program.statements.is_empty() => stmt.expression,
+            _ => {
+                return Err(Diagnostic {
+                    kind: ErrorKind::Syntax,
+                    ..Diagnostic::error("expected a single expression".to_string())
+                })
+            }
+        };
+
+        let mut env = Environment::new();
+        for (name, value) in self.bindings() {
+            env.set(name, value);
+        }
+        let mut quiet = Capabilities::new();
+        quiet.set_output(Box::new(io::sink()));
+        let mut builtins = self.builtins.clone();
+        for builtin in Builtins::core(quiet).iter() {
+            builtins.insert(builtin.clone());
+        }
+        let node = Node::Expression(Box::new(expression));
+        evaluator::eval_with_builtins(&node, &mut env, &self.limits, &builtins)
+            .map_err(Diagnostic::from)
+    }
+
+    /// Evaluates an already parsed program, such as one deserialized from
+    /// a tool that rewrote it. Its imports are loaded and bound first.
+    pub fn eval_program(&mut self, program: Program) -> Result<Object, Diagnostic> {
+        let mut budget = Budget::new(&self.limits);
+        self.run(program, &mut budget)
+    }
+
+    // The imports of a program spend the same fuel and time as the program
+    // itself, so a module cannot reset the budget.
+    fn run(&mut self, mut program: Program, budget: &mut Budget) -> Result<Object, Diagnostic> {
+        for stmt in &program.statements {
+            if let Statement::Import(ref import) = *stmt {
+                self.import(&import.path, budget)
+                    .and_then(|module| self.bind(&import.alias, module))
+                    .map_err(|e| Diagnostic {
+                        span: Some(import.span),
+                        ..Diagnostic::from(e)
+                    })?;
+            }
+        }
+        if self.optimize {
+            program = optimizer::optimize(program);
+        }
+        let node = Node::Program(Box::new(program));
+
+        match self.engine {
+            Engine::Tree => evaluator::eval_with_budget(
+                &node,
+                &mut self.env,
+                &self.limits,
+                &self.builtins,
+                budget,
+            )
+            .map_err(Diagnostic::from),
+            Engine::Vm => {
+                // Compile against a copy so that a program which fails to
+                // compile leaves no symbols behind.
+                let mut compiler = Compiler::new_with_state(self.compiler_state.clone());
+                compiler.compile(&node).map_err(Diagnostic::from)?;
+                let bytecode = compiler.bytecode();
+                self.compiler_state = compiler.into_state();
+
+                let globals = std::mem::take(&mut self.globals);
+                let mut vm = VM::new_with_globals(bytecode, globals);
+                if self.trace {
+                    vm.set_trace(Box::new(io::stderr()));
+                }
+                vm.set_limits(self.limits.clone());
+                vm.set_builtins(self.builtins.clone());
+                vm.set_global_names(self.compiler_state.symbol_table.slot_names());
+                let ran = vm.run_with_budget(budget);
+                let result = vm.result();
+                self.globals = vm.into_globals();
+                ran.map_err(Diagnostic::from)?;
+                Ok(result)
+            }
+        }
+    }
+
+    /// Evaluates the module at `path` in a session of its own, once, and
+    /// returns its bindings as a hash from names to values.
+    fn import(&mut self, path: &str, budget: &mut Budget) -> Result<Object, EvalError> {
+        let key = self.modules.resolve(&self.dir, path);
+        if let Some(module) = self.modules.get(&key) {
+            return Ok(module);
+        }
+        self.modules.begin(&key).map_err(EvalError::new)?;
+        let mut session = Session {
+            engine: self.engine,
+            optimize: self.optimize,
+            limits: self.limits.clone(),
+            max_parse_depth: self.max_parse_depth,
+            compiler_state: CompilerState::with_builtins(&self.builtins),
+            builtins: self.builtins.clone(),
+            modules: std::mem::take(&mut self.modules),
+            dir: key.parent().map(Path::to_path_buf).unwrap_or_default(),
+            ..Session::default()
+        };
+        let result = session
+            .modules
+            .source(&key)
+            .and_then(|source| {
+                // Spans in the module's source mean nothing to the importer.
+                session
+                    .eval_with_budget(&source, budget)
+                    .map_err(|d| EvalError {
+                        kind: d.kind,
+                        message: d.message,
+                        span: None,
+                    })
+            })
+            .map(|_| {
+                let exports = session.bindings().into_iter();
+                Object::Hash(exports.map(|(n, v)| (HashKey::Str(n), v)).collect())
+            });
+        self.modules = std::mem::take(&mut session.modules);
+        self.modules.finish(&key, result.as_ref().ok().cloned());
+        result.map_err(|e| EvalError {
+            kind: e.kind,
+            message: format!("{}: {}", key.display(), e.message),
+            span: None,
+        })
+    }
+
+    /// Returns the bound names and their values, sorted by name.
+    pub fn bindings(&self) -> Vec<(String, Object)> {
+        match self.engine {
+            Engine::Tree => self
+                .env
+                .names()
+                .into_iter()
+                .map(|name| {
+                    let value = self.env.get(&name).unwrap();
+                    (name, value)
+                })
+                .collect(),
+            Engine::Vm => self
+                .compiler_state
+                .symbol_table
+                .symbols()
+                .into_iter()
+                .filter(|s| s.scope == SymbolScope::Global)
+                // A binding whose initializer failed has a slot but no value.
+                .filter_map(|s| match self.globals.get(s.index) {
+                    Some(Some(v)) => Some((s.name, v.clone())),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the value bound to `name`, or else the builtin of that name.
+    pub fn get(&self, name: &str) -> Option<Object> {
+        let bound = match self.engine {
+            Engine::Tree => self.env.get(name),
+            Engine::Vm => self
+                .compiler_state
+                .symbol_table
+                .resolve(name)
+                .filter(|s| s.scope == SymbolScope::Global)
+                .and_then(|s| self.globals.get(s.index).cloned().flatten()),
+        };
+        bound.or_else(|| self.builtins.get(name).map(|b| Object::Builtin(b.clone())))
+    }
+
+    /// Binds `value` to `name` as if by `let`, without counting it against
+    /// the limits.
+    pub fn set(&mut self, name: &str, value: Object) {
+        match self.engine {
+            Engine::Tree => self.env.set(name.to_string(), value),
+            Engine::Vm => {
+                let symbol = self.compiler_state.symbol_table.define(name);
+                if symbol.index >= self.globals.len() {
+                    self.globals.resize(symbol.index + 1, None);
+                }
+                self.globals[symbol.index] = Some(value);
+            }
+        }
+    }
+
+    /// Binds `value` to `name` like `set`, but fails if that would grow the
+    /// bindings past `Limits::max_memory`.
+    fn bind(&mut self, name: &str, value: Object) -> Result<(), EvalError> {
+        let usage = match self.engine {
+            Engine::Tree => self.env.usage_after_set(name, &value),
+            Engine::Vm => {
+                let old = self
+                    .compiler_state
+                    .symbol_table
+                    .resolve(name)
+                    .filter(|s| s.scope == SymbolScope::Global)
+                    .and_then(|s| self.globals.get(s.index).cloned().flatten());
+                self.memory_usage() - old.map_or(0, |o| o.size()) + value.size()
+            }
+        };
+        evaluator::check_memory(&self.limits, usage)?;
+        self.set(name, value);
+        Ok(())
+    }
+
+    /// Approximate bytes held by the session's bindings, as counted against
+    /// `Limits::max_memory`.
+    pub fn memory_usage(&self) -> usize {
+        match self.engine {
+            Engine::Tree => self.env.memory_usage(),
+            Engine::Vm => self.globals.iter().flatten().map(Object::size).sum(),
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.bindings().into_iter().map(|(name, _)| name).collect()
+    }
+
+    /// Drops all bindings and loaded modules, keeping the engine, its
+    /// settings, the registered builtins and where modules come from.
+    pub fn reset(&mut self) {
+        let builtins = std::mem::take(&mut self.builtins);
+        let mut modules = std::mem::take(&mut self.modules);
+        modules.clear_loaded();
+        *self = Session {
+            modules,
+            dir: self.dir.clone(),
+            engine: self.engine,
+            trace: self.trace,
+            optimize: self.optimize,
+            limits: self.limits.clone(),
+            max_parse_depth: self.max_parse_depth,
+            ..Session::default()
+        };
+        for (index, builtin) in builtins.iter().enumerate() {
+            self.define_builtin(index, &builtin.name);
+        }
+        self.builtins = builtins;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use token::Span;
+
+    #[test]
+    fn engines_agree() {
+        let inputs = ["let b = 1103;", "let a = b * 2;", "a - b;"];
+
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut session = Session::new(engine);
+            let results: Vec<String> = inputs
+                .iter()
+                .map(|i| session.eval(i).unwrap().inspect())
+                .collect();
+            assert_eq!(vec!["1103", "2206", "1103"], results, "{}", engine);
+
+            let bindings: Vec<String> = session
+                .bindings()
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value.inspect()))
+                .collect();
+            assert_eq!(vec!["a=2206", "b=1103"], bindings, "{}", engine);
+
+            let err = session.eval("c;").unwrap_err();
+            assert_eq!("identifier not found: c", err.message, "{}", engine);
+            assert_eq!(ErrorKind::Runtime, err.kind);
+            let err = session.eval("1 + c;").unwrap_err();
+            assert_eq!(Some(Span { start: 4, end: 5 }), err.span, "{}", engine);
+            let err = session.eval("c +;").unwrap_err();
+            assert_eq!(ErrorKind::Syntax, err.kind);
+
+            session.reset();
+            assert!(session.bindings().is_empty());
+            assert_eq!(engine, session.engine());
+        }
+    }
+
+    #[test]
+    fn failed_let_stays_unbound() {
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut session = Session::new(engine);
+            let err = session.eval("let x = 1 / 0;").unwrap_err();
+            assert_eq!("division by zero", err.message, "{}", engine);
+            session.eval("let y = 1103;").unwrap();
+
+            let err = session.eval("x;").unwrap_err();
+            assert_eq!("identifier not found: x", err.message, "{}", engine);
+            assert!(session.get("x").is_none(), "{}", engine);
+            assert_eq!(vec!["y"], session.names(), "{}", engine);
+            session.eval("let x = 2;").unwrap();
+            assert_eq!("1105", session.eval("x + y;").unwrap().inspect());
+        }
+    }
+
+    #[test]
+    fn compile_error_defines_nothing() {
+        let mut session = Session::new(Engine::Vm);
+        let err = session.eval("let q = 1; nope;").unwrap_err();
+        assert_eq!("identifier not found: nope", err.message);
+
+        let err = session.eval("q;").unwrap_err();
+        assert_eq!("identifier not found: q", err.message);
+        assert!(session.names().is_empty());
+        session.eval("let q = 2;").unwrap();
+        assert_eq!("2", session.eval("q;").unwrap().inspect());
+    }
+
+    #[test]
+    fn optimize_before_executing() {
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut session = Session::new(engine);
+            session.set_optimize(true);
+
+            assert_eq!(
+                "1103",
+                session.eval("1; (1104-1103)*1103;").unwrap().inspect()
+            );
+            let err = session.eval("1103 / (1 - 1);").unwrap_err();
+            assert_eq!("division by zero", err.message, "{}", engine);
+        }
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let deep = format!("{}1{};", "(".repeat(100_000), ")".repeat(100_000));
+        let nested = format!("{}1{};", "(".repeat(400), ")".repeat(400));
+
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut session = Session::new(engine);
+            let err = session.eval(&deep).unwrap_err();
+            assert_eq!(
+                "stack overflow: expression nested deeper than 500",
+                err.message
+            );
+            assert_eq!(ErrorKind::StackOverflow, err.kind);
+
+            let limit = format!("{}1;", "-".repeat(parser::DEFAULT_MAX_DEPTH - 1));
+            assert_eq!("-1", session.eval(&limit).unwrap().inspect());
+
+            session.set_max_parse_depth(10);
+            assert!(session.eval(&nested).is_err());
+            session.set_max_parse_depth(1000);
+            assert_eq!("1", session.eval(&nested).unwrap().inspect());
+        }
+
+        let mut session = Session::new(Engine::Tree);
+        session.set_limits(Limits {
+            max_depth: 2,
+            ..Limits::default()
+        });
+        let err = session.eval("1 + 2 * 3;").unwrap_err();
+        assert_eq!(
+            "stack overflow: evaluation nested deeper than 2",
+            err.message
+        );
+        assert_eq!(ErrorKind::StackOverflow, err.kind);
+    }
+
+    #[test]
+    fn fuel_and_timeout() {
+        use std::time::Duration;
+
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut session = Session::new(engine);
+            session.set_limits(Limits {
+                fuel: Some(4),
+                ..Limits::default()
+            });
+            session.eval("let a = 1103;").unwrap();
+            let err = session.eval("a + a + a + a;").unwrap_err();
+            assert_eq!(
+                "out of fuel: evaluation took more than 4 steps",
+                err.message
+            );
+            assert_eq!(ErrorKind::OutOfFuel, err.kind);
+            assert_eq!("1103", session.eval("a;").unwrap().inspect());
+
+            session.set_limits(Limits {
+                timeout: Some(Duration::from_secs(0)),
+                ..Limits::default()
+            });
+            let err = session.eval("a;").unwrap_err();
+            assert_eq!("timeout: evaluation took longer than 0ns", err.message);
+            assert_eq!(ErrorKind::Timeout, err.kind);
+
+            session.set_limits(Limits::default());
+            assert_eq!("4412", session.eval("a + a + a + a;").unwrap().inspect());
+        }
+    }
+
+    #[test]
+    fn imports_share_the_budget() {
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut session = Session::new(engine);
+            session.add_module("sum.mk", "let x = 1 + 2 + 3 + 4;");
+            // Either program fits in 8 steps on its own, but not both.
+            session.set_limits(Limits {
+                fuel: Some(8),
+                ..Limits::default()
+            });
+            session.eval("1 + 1;").unwrap();
+            let err = session.eval("import \"sum.mk\" as s; 1 + 1;").unwrap_err();
+            assert_eq!(ErrorKind::OutOfFuel, err.kind, "{}", engine);
+
+            session.set_limits(Limits::default());
+            let result = session.eval("import \"sum.mk\" as s; s.x;");
+            assert_eq!("10", result.unwrap().inspect(), "{}", engine);
+            session.set_limits(Limits {
+                fuel: Some(8),
+                ..Limits::default()
+            });
+            session.eval("let y = 1 + 2 + 3 + 4;").unwrap();
+        }
+    }
+
+    #[test]
+    fn memory_limit() {
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut session = Session::new(engine);
+            session.set_limits(Limits {
+                max_memory: Some(2 * Object::Int(0).size() + 1),
+                ..Limits::default()
+            });
+            session.eval("let a = 1; let b = 2;").unwrap();
+            let usage = session.memory_usage();
+            assert_eq!(2 * Object::Int(0).size(), usage, "{}", engine);
+
+            let err = session.eval("let c = 3;").unwrap_err();
+            assert!(
+                err.message.starts_with("out of memory: "),
+                "{}",
+                err.message
+            );
+            assert_eq!(ErrorKind::OutOfMemory, err.kind);
+            assert_eq!(usage, session.memory_usage());
+
+            // Rebinding an existing name does not grow usage.
+            session.eval("let a = 1103;").unwrap();
+            assert_eq!(usage, session.memory_usage());
+            assert_eq!("1105", session.eval("a + b;").unwrap().inspect());
+        }
+    }
+
+    #[test]
+    fn virtual_modules() {
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut session = Session::new(engine);
+            let output = ::interpreter::CapturedOutput::new();
+            session.set_output(Box::new(output.clone()));
+            session.add_module(
+                "lib/math.mk",
+                "import \"consts.mk\" as c; let big = c.one * 1000;",
+            );
+            session.add_module("lib/consts.mk", "puts(1); let one = 1;");
+            session.add_module("a.mk", "import \"b.mk\" as b;");
+            session.add_module("b.mk", "import \"a.mk\" as a;");
+
+            let result = session.eval("import \"lib/math.mk\" as m; let one = 2; m.big + one;");
+            assert_eq!("1002", result.unwrap().inspect(), "{}", engine);
+            // Modules are evaluated once and share nothing with the importer.
+            session
+                .eval("import \"./lib/../lib/consts.mk\" as consts; consts.one;")
+                .unwrap();
+            assert_eq!("1\n", output.contents(), "{}", engine);
+            assert_eq!(
+                "{big: 1000, c: {one: 1}}",
+                session.get("m").unwrap().inspect()
+            );
+
+            let err = session.eval("m.small;").unwrap_err();
+            assert_eq!("unknown member: small", err.message, "{}", engine);
+            let err = session.eval("one.big;").unwrap_err();
+            assert_eq!("no member big in INTEGER", err.message, "{}", engine);
+            let err = session.eval("import \"a.mk\" as a;").unwrap_err();
+            assert_eq!(
+                "a.mk: b.mk: circular import: a.mk -> b.mk -> a.mk", err.message,
+                "{}",
+                engine
+            );
+            assert_eq!(Some(Span { start: 0, end: 19 }), err.span);
+            let err = session.eval("import \"missing.mk\" as x;").unwrap_err();
+            assert_eq!(
+                "missing.mk: permission denied: no filesystem access was granted",
+                err.message
+            );
+
+            // Virtual modules do not depend on where the script lives.
+            session.set_script_path(Path::new("/srv/scripts/main.mk"));
+            let result = session.eval("import \"lib/math.mk\" as math; math.big;");
+            assert_eq!("1000", result.unwrap().inspect(), "{}", engine);
+        }
+    }
+
+    #[test]
+    fn modules_count_against_memory() {
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut session = Session::new(engine);
+            session.add_module("big.mk", "let a = 1; let b = 2; let c = 3;");
+            session.set_limits(Limits {
+                max_memory: Some(4 * (1 + Object::Int(0).size())),
+                ..Limits::default()
+            });
+            let err = session.eval("import \"big.mk\" as big;").unwrap_err();
+            assert_eq!(ErrorKind::OutOfMemory, err.kind, "{}", engine);
+            assert!(session.get("big").is_none());
+            assert_eq!(0, session.memory_usage());
+        }
+    }
+
+    #[test]
+    fn registered_builtins() {
+        for engine in [Engine::Tree, Engine::Vm] {
+            let mut session = Session::new(engine);
+            session.eval("let sum = 1;").unwrap();
+            session.register_builtin("sum", 2, "", |_| Ok(Object::Int(0)));
+            session.register_builtin("max", 2, "Returns the larger integer.", |args| {
+                match (&args[0], &args[1]) {
+                    (Object::Int(a), Object::Int(b)) => Ok(Object::Int(*a.max(b))),
+                    _ => Err(evaluator::EvalError::new(
+                        "max: expected integers".to_string(),
+                    )),
+                }
+            });
+
+            assert_eq!("1103", session.eval("max(1, 1103);").unwrap().inspect());
+            assert_eq!("1", session.eval("sum;").unwrap().inspect());
+            let err = session.eval("max(1);").unwrap_err();
+            assert_eq!(
+                "wrong number of arguments to max: got 1, want 2",
+                err.message
+            );
+            let err = session.eval("max(1, max);").unwrap_err();
+            assert_eq!("max: expected integers", err.message);
+            assert_eq!(vec!["sum"], session.names());
+
+            session.reset();
+            assert_eq!(
+                "2",
+                session.eval("let max = max(1, 2); max;").unwrap().inspect()
+            );
+            assert_eq!("builtin sum", session.eval("sum;").unwrap().inspect());
+        }
+    }
+
+    #[test]
+    fn parse_engine() {
+        assert_eq!(Ok(Engine::Vm), "vm".parse());
+        assert_eq!(Ok(Engine::Tree), "tree".parse());
+        assert!("jit".parse::<Engine>().is_err());
+    }
+}
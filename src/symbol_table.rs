@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum SymbolScope {
+    Global,
+    Local,
+    Builtin,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub scope: SymbolScope,
+    pub index: usize,
+}
+
+/// Maps names to storage slots. The outermost table holds globals; each
+/// enclosed table holds the locals of one scope.
+#[derive(Debug, Default, Clone)]
+pub struct SymbolTable {
+    pub outer: Option<Box<SymbolTable>>,
+    store: HashMap<String, Symbol>,
+    num_definitions: usize,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    pub fn new_enclosed(outer: SymbolTable) -> SymbolTable {
+        SymbolTable {
+            outer: Some(Box::new(outer)),
+            ..SymbolTable::default()
+        }
+    }
+
+    pub fn define(&mut self, name: &str) -> Symbol {
+        // Redefining a name reuses its slot; a builtin is shadowed instead.
+        if let Some(symbol) = self.store.get(name) {
+            if symbol.scope != SymbolScope::Builtin {
+                return symbol.clone();
+            }
+        }
+
+        let scope = match self.outer {
+            Some(_) => SymbolScope::Local,
+            None => SymbolScope::Global,
+        };
+        let symbol = Symbol {
+            name: name.to_string(),
+            scope,
+            index: self.num_definitions,
+        };
+        self.store.insert(name.to_string(), symbol.clone());
+        self.num_definitions += 1;
+        symbol
+    }
+
+    /// Makes the builtin at `index` resolvable as `name`. It takes no slot.
+    pub fn define_builtin(&mut self, index: usize, name: &str) -> Symbol {
+        let symbol = Symbol {
+            name: name.to_string(),
+            scope: SymbolScope::Builtin,
+            index,
+        };
+        self.store.insert(name.to_string(), symbol.clone());
+        symbol
+    }
+
+    pub fn resolve(&self, name: &str) -> Option<Symbol> {
+        match self.store.get(name) {
+            Some(symbol) => Some(symbol.clone()),
+            None => self.outer.as_ref().and_then(|outer| outer.resolve(name)),
+        }
+    }
+
+    /// Returns the symbols defined in this scope, sorted by name.
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let mut symbols: Vec<Symbol> = self.store.values().cloned().collect();
+        symbols.sort_by(|a, b| a.name.cmp(&b.name));
+        symbols
+    }
+
+    /// Returns the names of the slots in this scope, indexed by slot.
+    pub fn slot_names(&self) -> Vec<String> {
+        let mut names = vec![String::new(); self.num_definitions];
+        for symbol in self.store.values() {
+            if symbol.scope != SymbolScope::Builtin {
+                names[symbol.index] = symbol.name.clone();
+            }
+        }
+        names
+    }
+
+    /// Returns the number of slots the scope needs.
+    pub fn num_definitions(&self) -> usize {
+        self.num_definitions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(name: &str, scope: SymbolScope, index: usize) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            scope,
+            index,
+        }
+    }
+
+    #[test]
+    fn define_and_resolve_global() {
+        let mut global = SymbolTable::new();
+
+        assert_eq!(symbol("a", SymbolScope::Global, 0), global.define("a"));
+        assert_eq!(symbol("b", SymbolScope::Global, 1), global.define("b"));
+        assert_eq!(symbol("a", SymbolScope::Global, 0), global.define("a"));
+
+        assert_eq!(
+            Some(symbol("b", SymbolScope::Global, 1)),
+            global.resolve("b")
+        );
+        assert_eq!(None, global.resolve("c"));
+        assert_eq!(2, global.num_definitions());
+        assert_eq!(
+            vec![
+                symbol("a", SymbolScope::Global, 0),
+                symbol("b", SymbolScope::Global, 1)
+            ],
+            global.symbols()
+        );
+    }
+
+    #[test]
+    fn define_and_resolve_builtin() {
+        let mut global = SymbolTable::new();
+        global.define("a");
+        assert_eq!(
+            symbol("len", SymbolScope::Builtin, 0),
+            global.define_builtin(0, "len")
+        );
+        assert_eq!(
+            Some(symbol("len", SymbolScope::Builtin, 0)),
+            global.resolve("len")
+        );
+        assert_eq!(1, global.num_definitions());
+
+        // A global of the same name shadows the builtin.
+        assert_eq!(symbol("len", SymbolScope::Global, 1), global.define("len"));
+        assert_eq!(
+            Some(symbol("len", SymbolScope::Global, 1)),
+            global.resolve("len")
+        );
+    }
+
+    #[test]
+    fn define_and_resolve_local() {
+        let mut global = SymbolTable::new();
+        global.define("a");
+
+        let mut local = SymbolTable::new_enclosed(global);
+        assert_eq!(symbol("b", SymbolScope::Local, 0), local.define("b"));
+        assert_eq!(symbol("a", SymbolScope::Local, 1), local.define("a"));
+
+        assert_eq!(Some(symbol("a", SymbolScope::Local, 1)), local.resolve("a"));
+        assert_eq!(Some(symbol("b", SymbolScope::Local, 0)), local.resolve("b"));
+
+        let global = *local.outer.take().unwrap();
+        assert_eq!(
+            Some(symbol("a", SymbolScope::Global, 0)),
+            global.resolve("a")
+        );
+        assert_eq!(None, global.resolve("b"));
+    }
+}
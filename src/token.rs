@@ -1,13 +1,28 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
+/// Byte offsets of a token in the source text.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The kind of a lexed token. New kinds are added as the language grows, so
+/// matches outside this crate need a wildcard arm.
+#[non_exhaustive]
 #[derive(Eq, PartialEq, Debug, Clone)]
-pub enum Token {
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TokenKind {
     Illegal,
-    EOF,
+    Eof,
 
     // 标识符
     Ident(String),
     Int(i64),
+    Str(String),
 
     // 操作符
     Assign,
@@ -18,6 +33,7 @@ pub enum Token {
 
     // 分隔符
     Comma,
+    Dot,
     Semicolon,
     Lparen,
     Rparen,
@@ -27,20 +43,158 @@ pub enum Token {
     // 关键字
     Function,
     Let,
+    Import,
+    As,
 }
 
-impl fmt::Display for Token {
+impl fmt::Display for TokenKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self {
-            Token::Int(value) => write!(f, "{}", value),
+            TokenKind::Int(value) => write!(f, "{}", value),
             tok => write!(f, "{:?}", tok),
         }
     }
 }
 
-pub fn lookup_ident(ident: String) -> Token {
+/// A token together with its location in the source text.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct SpannedToken {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+impl SpannedToken {
+    /// Formats the token as a JSON object, e.g.
+    /// `{"kind":"Ident","value":"five","start":4,"end":8}`.
+    pub fn to_json(&self) -> String {
+        let value = match self.kind {
+            TokenKind::Ident(ref name) => format!(",\"value\":{}", json_string(name)),
+            TokenKind::Int(value) => format!(",\"value\":{}", value),
+            TokenKind::Str(ref value) => format!(",\"value\":{}", json_string(value)),
+            _ => String::new(),
+        };
+        format!(
+            "{{\"kind\":\"{}\"{},\"start\":{},\"end\":{}}}",
+            self.kind.name(),
+            value,
+            self.span.start,
+            self.span.end
+        )
+    }
+}
+
+/// Quotes `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl TokenKind {
+    /// Returns the name of the kind without its value, e.g. `Ident`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenKind::Illegal => "Illegal",
+            TokenKind::Eof => "Eof",
+            TokenKind::Ident(_) => "Ident",
+            TokenKind::Int(_) => "Int",
+            TokenKind::Str(_) => "Str",
+            TokenKind::Assign => "Assign",
+            TokenKind::Plus => "Plus",
+            TokenKind::Minus => "Minus",
+            TokenKind::Asterisk => "Asterisk",
+            TokenKind::Slash => "Slash",
+            TokenKind::Comma => "Comma",
+            TokenKind::Dot => "Dot",
+            TokenKind::Semicolon => "Semicolon",
+            TokenKind::Lparen => "Lparen",
+            TokenKind::Rparen => "Rparen",
+            TokenKind::Lbrace => "Lbrace",
+            TokenKind::Rbrace => "Rbrace",
+            TokenKind::Function => "Function",
+            TokenKind::Let => "Let",
+            TokenKind::Import => "Import",
+            TokenKind::As => "As",
+        }
+    }
+
+    /// Returns the source text the token was lexed from.
+    pub fn literal(&self) -> String {
+        match self {
+            TokenKind::Illegal => "ILLEGAL".to_string(),
+            TokenKind::Eof => "".to_string(),
+            TokenKind::Ident(name) => name.clone(),
+            TokenKind::Int(value) => value.to_string(),
+            TokenKind::Str(value) => format!("\"{}\"", value),
+            TokenKind::Assign => "=".to_string(),
+            TokenKind::Plus => "+".to_string(),
+            TokenKind::Minus => "-".to_string(),
+            TokenKind::Asterisk => "*".to_string(),
+            TokenKind::Slash => "/".to_string(),
+            TokenKind::Comma => ",".to_string(),
+            TokenKind::Dot => ".".to_string(),
+            TokenKind::Semicolon => ";".to_string(),
+            TokenKind::Lparen => "(".to_string(),
+            TokenKind::Rparen => ")".to_string(),
+            TokenKind::Lbrace => "{".to_string(),
+            TokenKind::Rbrace => "}".to_string(),
+            TokenKind::Function => "fn".to_string(),
+            TokenKind::Let => "let".to_string(),
+            TokenKind::Import => "import".to_string(),
+            TokenKind::As => "as".to_string(),
+        }
+    }
+}
+
+pub fn lookup_ident(ident: String) -> TokenKind {
     match ident.as_str() {
-        "let" => Token::Let,
-        _ => Token::Ident(ident),
+        "let" => TokenKind::Let,
+        "import" => TokenKind::Import,
+        "as" => TokenKind::As,
+        _ => TokenKind::Ident(ident),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spanned_token_to_json() {
+        let tests = vec![
+            (
+                TokenKind::Ident("five".to_string()),
+                r#"{"kind":"Ident","value":"five","start":4,"end":8}"#,
+            ),
+            (
+                TokenKind::Int(-5),
+                r#"{"kind":"Int","value":-5,"start":4,"end":8}"#,
+            ),
+            (TokenKind::Assign, r#"{"kind":"Assign","start":4,"end":8}"#),
+            (
+                TokenKind::Str("a\"b\\c\n\t\u{1}\u{7f}é".to_string()),
+                "{\"kind\":\"Str\",\"value\":\"a\\\"b\\\\c\\n\\t\\u0001\u{7f}é\",\"start\":4,\"end\":8}",
+            ),
+        ];
+
+        for t in tests {
+            let tok = SpannedToken {
+                kind: t.0,
+                span: Span { start: 4, end: 8 },
+            };
+            assert_eq!(t.1, tok.to_json());
+        }
     }
 }
@@ -0,0 +1,403 @@
+use builtins::Builtins;
+use code::{format_instruction, read_u16, Instructions, Opcode};
+use compiler::Bytecode;
+use evaluator::{self, check_memory, Budget, ErrorKind, EvalError, Limits};
+use object::Object;
+use std::convert::TryFrom;
+use std::io;
+use token::TokenKind;
+
+const STACK_SIZE: usize = 2048;
+
+#[derive(Debug)]
+pub struct VmError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl VmError {
+    pub fn new(message: String) -> VmError {
+        VmError {
+            kind: ErrorKind::Runtime,
+            message,
+        }
+    }
+}
+
+impl From<EvalError> for VmError {
+    fn from(e: EvalError) -> VmError {
+        VmError {
+            kind: e.kind,
+            message: e.message,
+        }
+    }
+}
+
+pub type VmResult = Result<(), VmError>;
+
+/// Executes compiled bytecode on a value stack.
+pub struct VM {
+    constants: Vec<Object>,
+    instructions: Instructions,
+    stack: Vec<Object>,
+    // A slot is `None` until its `let` succeeds, so a binding whose
+    // initializer failed stays undefined.
+    globals: Vec<Option<Object>>,
+    // The names of the global slots, if known, for error messages.
+    global_names: Vec<String>,
+    // The value of the last statement, matching what `evaluator::eval`
+    // returns for a program.
+    result: Object,
+    trace: Option<Box<dyn io::Write>>,
+    limits: Limits,
+    builtins: Builtins,
+    // Approximate bytes held by `globals`.
+    memory: usize,
+}
+
+impl VM {
+    pub fn new(bytecode: Bytecode) -> VM {
+        VM::new_with_globals(bytecode, Vec::new())
+    }
+
+    /// Creates a VM that continues with the globals of an earlier run, as the
+    /// REPL does between inputs.
+    pub fn new_with_globals(bytecode: Bytecode, globals: Vec<Option<Object>>) -> VM {
+        let memory = globals.iter().flatten().map(Object::size).sum();
+        VM {
+            constants: bytecode.constants,
+            instructions: bytecode.instructions,
+            stack: Vec::with_capacity(STACK_SIZE),
+            globals,
+            global_names: Vec::new(),
+            result: Object::Int(0),
+            trace: None,
+            limits: Limits::default(),
+            builtins: Builtins::new(),
+            memory,
+        }
+    }
+
+    /// Logs every executed instruction and the resulting stack top to `sink`.
+    pub fn set_trace(&mut self, sink: Box<dyn io::Write>) {
+        self.trace = Some(sink);
+    }
+
+    /// Bounds the fuel, time and global memory of each `run`; the fuel counts
+    /// executed instructions.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Sets the builtins that `OpGetBuiltin` operands index into.
+    pub fn set_builtins(&mut self, builtins: Builtins) {
+        self.builtins = builtins;
+    }
+
+    /// Names the global slots, so that reading an undefined one reports the
+    /// identifier rather than the slot.
+    pub fn set_global_names(&mut self, names: Vec<String>) {
+        self.global_names = names;
+    }
+
+    pub fn result(&self) -> Object {
+        self.result.clone()
+    }
+
+    /// Approximate bytes held by the globals.
+    pub fn memory_usage(&self) -> usize {
+        self.memory
+    }
+
+    pub fn into_globals(self) -> Vec<Option<Object>> {
+        self.globals
+    }
+
+    pub fn run(&mut self) -> VmResult {
+        let mut budget = Budget::new(&self.limits);
+        self.run_with_budget(&mut budget)
+    }
+
+    /// Runs like `run`, counting steps against `budget`, which may be shared
+    /// with other runs.
+    pub fn run_with_budget(&mut self, budget: &mut Budget) -> VmResult {
+        let mut ip = 0;
+        while ip < self.instructions.len() {
+            budget.step()?;
+            let start = ip;
+            let op = Opcode::try_from(self.instructions[ip]).map_err(VmError::new)?;
+            ip += 1;
+
+            match op {
+                Opcode::Constant => {
+                    let index = read_u16(&self.instructions[ip..]) as usize;
+                    ip += 2;
+                    let constant = self.constants[index].clone();
+                    self.push(constant)?;
+                }
+                Opcode::Pop => {
+                    self.result = self.pop()?;
+                }
+                Opcode::Add | Opcode::Sub | Opcode::Mul | Opcode::Div => {
+                    self.execute_binary_operation(op)?;
+                }
+                Opcode::Minus => {
+                    let right = self.pop()?;
+                    let value = evaluator::eval_prefix_operator(&TokenKind::Minus, &right)?;
+                    self.push(value)?
+                }
+                Opcode::SetGlobal => {
+                    let index = read_u16(&self.instructions[ip..]) as usize;
+                    ip += 2;
+                    let value = self.pop()?;
+                    let old = self.globals.get(index).and_then(Option::as_ref);
+                    let usage = self.memory - old.map_or(0, Object::size) + value.size();
+                    check_memory(&self.limits, usage)?;
+                    self.memory = usage;
+                    if index >= self.globals.len() {
+                        self.globals.resize(index + 1, None);
+                    }
+                    self.globals[index] = Some(value.clone());
+                    self.result = value;
+                }
+                Opcode::GetGlobal => {
+                    let index = read_u16(&self.instructions[ip..]) as usize;
+                    ip += 2;
+                    let value = match self.globals.get(index) {
+                        Some(Some(value)) => value.clone(),
+                        _ => return Err(self.undefined_global(index)),
+                    };
+                    self.push(value)?;
+                }
+                Opcode::GetBuiltin => {
+                    let index = self.instructions[ip] as usize;
+                    ip += 1;
+                    let builtin = match self.builtins.by_index(index) {
+                        Some(builtin) => builtin.clone(),
+                        None => {
+                            return Err(VmError::new(format!("builtin {} is not defined", index)))
+                        }
+                    };
+                    self.push(Object::Builtin(builtin))?;
+                }
+                Opcode::Call => {
+                    let num_args = self.instructions[ip] as usize;
+                    ip += 1;
+                    if self.stack.len() <= num_args {
+                        return Err(VmError::new("stack underflow".to_string()));
+                    }
+                    let args = self.stack.split_off(self.stack.len() - num_args);
+                    let function = self.pop()?;
+                    let value = evaluator::apply_function(&function, &args)?;
+                    self.push(value)?;
+                }
+                Opcode::Member => {
+                    let name = self.pop()?;
+                    let object = self.pop()?;
+                    let value = match name {
+                        Object::Str(ref name) => evaluator::eval_member(&object, name)?,
+                        obj => {
+                            return Err(VmError::new(format!(
+                                "member name must be a STRING, got {}",
+                                obj.type_name()
+                            )))
+                        }
+                    };
+                    self.push(value)?;
+                }
+            }
+
+            if self.trace.is_some() {
+                self.trace_instruction(start)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn trace_instruction(&mut self, offset: usize) -> VmResult {
+        let (text, _) = format_instruction(&self.instructions, offset);
+        let top = match self.stack.last() {
+            Some(obj) => obj.inspect(),
+            None => "-".to_string(),
+        };
+        let sink = self.trace.as_mut().unwrap();
+        writeln!(sink, "{:04} {:<16} top: {}", offset, text, top)
+            .map_err(|e| VmError::new(format!("trace: {}", e)))
+    }
+
+    fn execute_binary_operation(&mut self, op: Opcode) -> VmResult {
+        let right = self.pop()?;
+        let left = self.pop()?;
+        let operator = match op {
+            Opcode::Add => TokenKind::Plus,
+            Opcode::Sub => TokenKind::Minus,
+            Opcode::Mul => TokenKind::Asterisk,
+            Opcode::Div => TokenKind::Slash,
+            _ => return Err(VmError::new(format!("unknown integer operator {:?}", op))),
+        };
+        let value = evaluator::eval_infix_operator(&operator, &left, &right)?;
+        self.push(value)
+    }
+
+    fn undefined_global(&self, index: usize) -> VmError {
+        match self.global_names.get(index) {
+            Some(name) if !name.is_empty() => {
+                VmError::new(format!("identifier not found: {}", name))
+            }
+            _ => VmError::new(format!("global {} is not defined", index)),
+        }
+    }
+
+    fn push(&mut self, obj: Object) -> VmResult {
+        if self.stack.len() >= STACK_SIZE {
+            return Err(VmError::new("stack overflow".to_string()));
+        }
+        self.stack.push(obj);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<Object, VmError> {
+        self.stack
+            .pop()
+            .ok_or_else(|| VmError::new("stack underflow".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use compiler::Compiler;
+    use environment::Environment;
+    use evaluator;
+    use parser;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run(input: &str) -> Object {
+        let node = parser::parse(input).expect(input);
+        let mut compiler = Compiler::new();
+        compiler.compile(&node).expect(input);
+        let mut vm = VM::new(compiler.bytecode());
+        vm.run().expect(input);
+        vm.result()
+    }
+
+    #[test]
+    fn run_matches_evaluator() {
+        let tests = vec![
+            "1103;",
+            "-1103;",
+            "2206-1103;",
+            "1103-1103+1103;",
+            "-1103+1103*2;",
+            "(1104-1103)*1103;",
+            "7 / 2; 7 / -2;",
+            "let birthday = 1103;",
+            "let birthday = 1103; birthday;",
+            "let a = 1103; let b = a * 2; b - a;",
+            "let a = 1; let a = a + 1; a;",
+            "",
+        ];
+
+        for t in tests {
+            let node = parser::parse(t).expect(t);
+            let expected = evaluator::eval(&node, &mut Environment::new()).expect(t);
+            assert_eq!(expected.inspect(), run(t).inspect(), "{}", t);
+        }
+    }
+
+    #[test]
+    fn trace_instructions() {
+        let node = parser::parse("let a = 1103; -a;").unwrap();
+        let mut compiler = Compiler::new();
+        compiler.compile(&node).unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        vm.set_trace(Box::new(SharedBuffer(buffer.clone())));
+        vm.run().unwrap();
+
+        let expected = "0000 OpConstant 0     top: 1103
+0003 OpSetGlobal 0    top: -
+0006 OpGetGlobal 0    top: 1103
+0009 OpMinus          top: -1103
+0010 OpPop            top: -
+";
+        assert_eq!(
+            expected,
+            String::from_utf8(buffer.borrow().clone()).unwrap()
+        );
+    }
+
+    #[test]
+    fn run_errors_match_evaluator() {
+        let tests = vec![
+            "1103 / 0;",
+            "9223372036854775807 * 2;",
+            "-(-9223372036854775807 - 1);",
+        ];
+
+        for t in tests {
+            let node = parser::parse(t).expect(t);
+            let expected = evaluator::eval(&node, &mut Environment::new()).unwrap_err();
+
+            let mut compiler = Compiler::new();
+            compiler.compile(&node).expect(t);
+            let mut vm = VM::new(compiler.bytecode());
+            assert_eq!(expected.message, vm.run().unwrap_err().message, "{}", t);
+        }
+    }
+
+    #[test]
+    fn run_invalid_bytecode() {
+        use code::make;
+
+        let tests = vec![
+            (make(Opcode::Pop, &[]), "stack underflow"),
+            (make(Opcode::GetGlobal, &[0]), "global 0 is not defined"),
+        ];
+
+        for t in tests {
+            let mut vm = VM::new(Bytecode {
+                instructions: t.0,
+                constants: Vec::new(),
+            });
+            assert_eq!(t.1, vm.run().unwrap_err().message);
+        }
+
+        let mut vm = VM::new(Bytecode {
+            instructions: make(Opcode::GetGlobal, &[0]),
+            constants: Vec::new(),
+        });
+        vm.set_global_names(vec!["x".to_string()]);
+        assert_eq!("identifier not found: x", vm.run().unwrap_err().message);
+    }
+
+    #[test]
+    fn globals_persist_between_runs() {
+        let mut compiler = Compiler::new();
+        compiler
+            .compile(&parser::parse("let a = 1103;").unwrap())
+            .unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        vm.run().unwrap();
+        let globals = vm.into_globals();
+
+        let mut compiler = Compiler::new_with_state(compiler.into_state());
+        compiler.compile(&parser::parse("a * 2;").unwrap()).unwrap();
+        let mut vm = VM::new_with_globals(compiler.bytecode(), globals);
+        vm.run().unwrap();
+        assert_eq!("2206", vm.result().inspect());
+    }
+}
@@ -0,0 +1,66 @@
+//! JavaScript bindings for a browser playground, built for
+//! `wasm32-unknown-unknown` with the `wasm` feature:
+//!
+//! ```js
+//! const { output, result, error, error_kind } = evaluate("puts(1); 2;");
+//! ```
+
+use interpreter::{CapturedOutput, Interpreter};
+use wasm_bindgen::prelude::*;
+
+/// What `evaluate` returns: everything `puts` printed, and either the inspected
+/// result or a rendered error with its kind, e.g. `"OutOfFuel"`.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone)]
+pub struct EvalOutput {
+    pub output: String,
+    pub result: Option<String>,
+    pub error: Option<String>,
+    pub error_kind: Option<String>,
+}
+
+/// Evaluates `source` in a fresh interpreter. It is exported to JavaScript
+/// as `evaluate`, since `eval` cannot be bound in a strict-mode module.
+#[wasm_bindgen(js_name = "evaluate")]
+pub fn eval_source(source: &str) -> EvalOutput {
+    let mut interp = Interpreter::new();
+    let output = CapturedOutput::new();
+    interp.set_output(Box::new(output.clone()));
+    let (result, error, error_kind) = match interp.eval_str(source) {
+        Ok(value) => (Some(value.inspect()), None, None),
+        Err(d) => (
+            None,
+            Some(d.render(source, false)),
+            Some(format!("{:?}", d.kind)),
+        ),
+    };
+    EvalOutput {
+        output: output.contents(),
+        result,
+        error,
+        error_kind,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn captures_output() {
+        let out = eval_source("let a = 1103; puts(a); a + 1;");
+        assert_eq!("1103\n", out.output);
+        assert_eq!(Some("1104".to_string()), out.result);
+        assert_eq!(None, out.error);
+        assert_eq!(None, out.error_kind);
+
+        let out = eval_source("puts(1); b;");
+        assert_eq!("1\n", out.output);
+        assert_eq!(None, out.result);
+        assert!(out.error.unwrap().contains("identifier not found: b"));
+        assert_eq!(Some("Runtime".to_string()), out.error_kind);
+
+        let out = eval_source("1 +;");
+        assert_eq!(Some("Syntax".to_string()), out.error_kind);
+    }
+}
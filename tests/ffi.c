@@ -0,0 +1,76 @@
+#include <monkey.h>
+#include <stdio.h>
+#include <string.h>
+
+#define CHECK(cond)                                                      \
+    do {                                                                 \
+        if (!(cond)) {                                                   \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__,       \
+                    __LINE__, #cond);                                    \
+            return 1;                                                    \
+        }                                                                \
+    } while (0)
+
+static MonkeyValue *add(void *user_data, const MonkeyValue *const *args, size_t nargs,
+                        const char **error) {
+    int64_t a, b;
+    int *calls = user_data;
+    (*calls)++;
+    if (nargs != 2 || !monkey_value_as_int(args[0], &a) || !monkey_value_as_int(args[1], &b)) {
+        *error = "add: expected two integers";
+        return NULL;
+    }
+    return monkey_value_int(a + b);
+}
+
+int main(void) {
+    int calls = 0;
+    int64_t i;
+    char *s;
+    MonkeyInterpreter *interp = monkey_interpreter_new();
+    CHECK(monkey_register_builtin(interp, "add", 2, add, &calls));
+
+    MonkeyResult *result = monkey_eval(interp, "let a = 1100; add(a, 3);");
+    CHECK(monkey_result_is_ok(result));
+    CHECK(monkey_result_error(result) == NULL);
+    CHECK(monkey_result_error_kind(result) == MONKEY_ERROR_NONE);
+    const MonkeyValue *value = monkey_result_value(result);
+    CHECK(monkey_value_type(value) == MONKEY_INTEGER);
+    CHECK(monkey_value_as_int(value, &i) && i == 1103);
+    s = monkey_value_inspect(value);
+    CHECK(strcmp(s, "1103") == 0);
+    monkey_string_free(s);
+    CHECK(monkey_value_as_string(value) == NULL);
+    monkey_result_free(result);
+    CHECK(calls == 1);
+
+    result = monkey_eval(interp, "add(a, add);");
+    CHECK(!monkey_result_is_ok(result));
+    CHECK(monkey_result_value(result) == NULL);
+    CHECK(strcmp(monkey_result_error(result), "add: expected two integers") == 0);
+    CHECK(monkey_result_error_kind(result) == MONKEY_ERROR_RUNTIME);
+    monkey_result_free(result);
+
+    result = monkey_eval(interp, "a / 0;");
+    CHECK(strcmp(monkey_result_error(result), "division by zero") == 0);
+    monkey_result_free(result);
+
+    result = monkey_eval(interp, "a +;");
+    CHECK(monkey_result_error_kind(result) == MONKEY_ERROR_SYNTAX);
+    monkey_result_free(result);
+
+    result = monkey_eval(interp, "puts(a);");
+    CHECK(monkey_result_error_kind(result) == MONKEY_ERROR_PERMISSION_DENIED);
+    monkey_result_free(result);
+
+    MonkeyValue *str = monkey_value_string("monkey");
+    CHECK(monkey_value_type(str) == MONKEY_STRING);
+    s = monkey_value_as_string(str);
+    CHECK(strcmp(s, "monkey") == 0);
+    monkey_string_free(s);
+    monkey_value_free(str);
+
+    monkey_interpreter_free(interp);
+    printf("ok\n");
+    return 0;
+}
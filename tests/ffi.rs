@@ -0,0 +1,66 @@
+#![cfg(unix)]
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The directories cargo may build the library into: `deps`, where the
+/// test binary lives, and the profile directory it is copied up to.
+fn lib_dirs() -> Vec<PathBuf> {
+    let exe = env::current_exe().unwrap();
+    let deps = exe.parent().unwrap().to_path_buf();
+    let profile = deps.parent().unwrap().to_path_buf();
+    vec![deps, profile]
+}
+
+#[test]
+fn c_program() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let lib_dirs = lib_dirs();
+    let out = lib_dirs[0].join("ffi_c_test");
+
+    let mut command = Command::new(env::var("CC").unwrap_or_else(|_| "cc".to_string()));
+    command
+        .arg(root.join("tests/ffi.c"))
+        .arg("-I")
+        .arg(root.join("include"));
+    for dir in &lib_dirs {
+        command
+            .arg("-L")
+            .arg(dir)
+            .arg(format!("-Wl,-rpath,{}", dir.display()));
+    }
+    let status = command
+        .arg("-lmonkey")
+        .arg("-o")
+        .arg(&out)
+        .status()
+        .unwrap();
+    assert!(status.success(), "compiling tests/ffi.c failed");
+
+    let output = Command::new(&out).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!("ok\n", String::from_utf8_lossy(&output.stdout));
+}
+
+#[test]
+fn header_declares_every_export() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let header = fs::read_to_string(root.join("include/monkey.h")).unwrap();
+    let source = fs::read_to_string(root.join("src/ffi.rs")).unwrap();
+    for line in source.lines() {
+        if let Some(rest) = line.split("extern \"C\" fn ").nth(1) {
+            let name = rest.split('(').next().unwrap();
+            assert!(
+                header.contains(&format!("{}(", name)),
+                "{} is not in monkey.h",
+                name
+            );
+        }
+    }
+}